@@ -2,6 +2,8 @@ use candid::{CandidType, Principal};
 use evm_rpc_types::{RpcConfig, RpcServices};
 use serde::Deserialize;
 
+use crate::types::config::Config;
+
 #[derive(Clone, CandidType, Deserialize)]
 pub struct ChainConfig {
   pub chain_id: u32,
@@ -12,6 +14,20 @@ pub struct ChainConfig {
 }
 
 impl ChainConfig {
+  /// The chain config following a change of the canister config
+  pub fn with_config(&self, config: &Config) -> Self {
+    let rpc_config = self.rpc_config.clone().unwrap_or_default();
+
+    Self {
+      evm_rpc_canister: config.evm_rpc_canister,
+      rpc_config: Some(RpcConfig {
+        response_size_estimate: Some(config.response_size_estimate()),
+        ..rpc_config
+      }),
+      ..self.clone()
+    }
+  }
+
   pub fn rpc_providers_len(&self) -> usize {
     match &self.rpc_providers {
       RpcServices::Custom { services, .. } => services.len(),
@@ -24,3 +40,39 @@ impl ChainConfig {
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use evm_rpc_types::ConsensusStrategy;
+
+  use super::*;
+
+  #[test]
+  fn test_with_config_keeps_chain_settings() {
+    let chain_config = ChainConfig {
+      chain_id: 1,
+      rpc_providers: RpcServices::EthMainnet(None),
+      evm_rpc_canister: Principal::from_slice(&[1]),
+      rpc_config: Some(RpcConfig {
+        response_size_estimate: Some(1_000),
+        response_consensus: Some(ConsensusStrategy::Equality),
+      }),
+      monitoring_interval_sec: 15,
+    };
+    let config = Config {
+      evm_rpc_canister: Principal::from_slice(&[2]),
+      proxy_canister: Principal::from_slice(&[3]),
+      estimate_events_num: 10,
+      max_response_bytes: 1_000_000,
+    };
+
+    let updated = chain_config.with_config(&config);
+
+    assert_eq!(updated.chain_id, 1);
+    assert_eq!(updated.evm_rpc_canister, Principal::from_slice(&[2]));
+    assert_eq!(updated.monitoring_interval_sec, 15);
+    let rpc_config = updated.rpc_config.unwrap();
+    assert_eq!(rpc_config.response_size_estimate, Some(8_000));
+    assert_eq!(rpc_config.response_consensus, Some(ConsensusStrategy::Equality));
+  }
+}
@@ -3,19 +3,124 @@ use evm_logs_types::Event;
 use evm_rpc_types::LogEntry;
 
 use super::service::ChainService;
-use crate::{internals::misc::timestamp_millis, subscription_manager::events_publisher::publish_events};
-
-pub async fn process_and_publish_events(service: &ChainService, logs: Vec<LogEntry>) {
-  let events: Vec<Event> = logs
-    .iter()
-    .enumerate()
-    .map(|(index, log)| Event {
-      id: Nat::from(index as u64 + 1),
+use crate::{
+  STATE,
+  constants::SEEN_LOGS_WINDOW,
+  internals::misc::timestamp_millis,
+  subscription_manager::events_publisher::{next_notification_id, publish_events},
+  types::seen_logs::SeenLogs,
+};
+
+/// Bits reserved for the log index and the block number in a derived event id
+const LOG_INDEX_BITS: u32 = 32;
+const BLOCK_NUMBER_BITS: u32 = 64;
+
+pub async fn process_and_publish_events(service: &ChainService, mut logs: Vec<LogEntry>) {
+  retain_unseen_logs(service.config.chain_id, &mut logs);
+
+  publish_events(logs_to_events(service.config.chain_id, logs)).await;
+}
+
+/// Events in block order, see `sort_logs`
+pub fn logs_to_events(chain_id: u32, mut logs: Vec<LogEntry>) -> Vec<Event> {
+  sort_logs(&mut logs);
+
+  logs
+    .into_iter()
+    .map(|log| Event {
+      id: event_id(chain_id, &log).unwrap_or_else(next_notification_id),
       timestamp: timestamp_millis(),
-      chain_id: service.config.chain_id,
-      log_entry: log.clone(),
+      chain_id,
+      log_entry: log,
     })
-    .collect();
+    .collect()
+}
+
+/// Drops logs that were already published, e.g. fetched again by overlapping ranges or chunks.
+fn retain_unseen_logs(chain_id: u32, logs: &mut Vec<LogEntry>) {
+  STATE.with(|state| {
+    let seen_logs = &mut state.borrow_mut().seen_logs;
+    logs.retain(|log| SeenLogs::key(chain_id, log).is_none_or(|key| seen_logs.insert(key, SEEN_LOGS_WINDOW)));
+  });
+}
+
+/// Chunked `eth_getLogs` results are merged in completion order, restore the block order.
+fn sort_logs(logs: &mut [LogEntry]) {
+  logs.sort_by_cached_key(|log| {
+    (
+      log.block_number.clone().map(Nat::from),
+      log.transaction_index.clone().map(Nat::from),
+      log.log_index.clone().map(Nat::from),
+    )
+  });
+}
+
+/// Id stable across fetches and unique across chains: `chain_id | block_number | log_index`.
+/// `None` for logs without a block number or log index (pending ones).
+fn event_id(chain_id: u32, log: &LogEntry) -> Option<Nat> {
+  let block_number = Nat::from(log.block_number.clone()?);
+  let log_index = Nat::from(log.log_index.clone()?);
+
+  let block_shift = Nat::from(1u128 << BLOCK_NUMBER_BITS);
+  let log_index_shift = Nat::from(1u64 << LOG_INDEX_BITS);
+
+  Some((Nat::from(chain_id) * block_shift + block_number) * log_index_shift + log_index)
+}
+
+#[cfg(test)]
+mod tests {
+  use std::str::FromStr;
+
+  use evm_rpc_types::{Hex, Hex20};
+
+  use super::*;
+
+  fn create_log(block_number: u64, transaction_index: u64, log_index: u64) -> LogEntry {
+    LogEntry {
+      address: Hex20::from_str("0xd42AcA6E135D1dae6317e776F7EB96Eb91b8eb91").unwrap(),
+      topics: vec![],
+      data: Hex::from(vec![]),
+      block_number: Some(block_number.into()),
+      transaction_hash: None,
+      transaction_index: Some(transaction_index.into()),
+      block_hash: None,
+      log_index: Some(log_index.into()),
+      removed: false,
+    }
+  }
+
+  #[test]
+  fn test_sort_logs_by_block_transaction_and_log_index() {
+    let mut logs = vec![
+      create_log(11, 0, 4),
+      create_log(10, 2, 7),
+      create_log(10, 1, 3),
+      create_log(10, 2, 5),
+    ];
+    sort_logs(&mut logs);
+
+    let log_indexes: Vec<Nat> = logs.into_iter().map(|log| Nat::from(log.log_index.unwrap())).collect();
+    assert_eq!(
+      log_indexes,
+      vec![Nat::from(3u32), Nat::from(5u32), Nat::from(7u32), Nat::from(4u32)]
+    );
+  }
+
+  #[test]
+  fn test_event_id_is_unique_per_chain_block_and_log() {
+    let id = event_id(1, &create_log(10, 0, 2)).unwrap();
+
+    assert_eq!(id, event_id(1, &create_log(10, 5, 2)).unwrap());
+    assert_ne!(id, event_id(2, &create_log(10, 0, 2)).unwrap());
+    assert_ne!(id, event_id(1, &create_log(11, 0, 2)).unwrap());
+    assert_ne!(id, event_id(1, &create_log(10, 0, 3)).unwrap());
+  }
+
+  #[test]
+  fn test_event_id_requires_position() {
+    let mut log = create_log(10, 0, 2);
+    log.log_index = None;
 
-  publish_events(events).await;
+    assert!(event_id(1, &log).is_none());
+  }
 }
@@ -1,59 +1,136 @@
 use std::str::FromStr;
 
-use candid::{Encode, Nat};
+use candid::Nat;
 use canister_utils::debug_log;
+use evm_logs_types::{BalanceChangeKind, Filter, SubscriptionInfo};
 use evm_rpc_types::{BlockTag, GetLogsArgs, Hex20, Hex32, LogEntry, MultiRpcResult, Nat256, RpcResult};
 use futures::future::join_all;
-use ic_cdk::api::call::call_with_payment128;
+use ic_cdk::api::call::{call_with_payment128, msg_cycles_refunded128};
 
 use super::{ChainConfig, utils::*};
 use crate::{
+  STATE,
   constants::*,
   get_state_value, log_with_metrics,
-  types::balances::{BalanceError, Balances},
+  subscription_manager::stats::record_charged,
+  types::{
+    balance_ledger::{ChargeReference, record_balance_change},
+    balances::{BalanceError, Balances},
+    pricing::{fetch_price, get_pricing},
+  },
 };
 
 const BASE_STRUCT_SIZE: usize = 8;
 const MAX_RETRIES: usize = 2;
 const CYCLES_TO_RECEIVE_LOGS: u128 = 10_000_000_000;
 
-fn estimate_log_entry_size(logs: &[LogEntry]) -> usize {
-  logs.iter().map(|log| Encode!(log).unwrap().len()).sum()
+/// Logs returned by `eth_getLogs` and the cycles the EVM RPC canister kept for them, retries included
+pub struct FetchedLogs {
+  pub logs: Vec<LogEntry>,
+  pub cycles_used: u128,
 }
 
-fn estimate_cycles_used(
-  logs_received: &[LogEntry],
-  addresses_count: usize,
-  topics_count: Option<&Vec<Vec<Hex32>>>,
-) -> u64 {
-  log_with_metrics!("calculating cycles used for logs: {}", logs_received.len());
+/// Request-side cost of an `eth_getLogs` call, a lower bound used only to check a balance up front.
+pub fn estimate_fetch_cycles(addresses_count: usize, topics_count: Option<&Vec<Vec<Hex32>>>) -> u64 {
   // Estimate request size
   let request_size_bytes = BASE_STRUCT_SIZE
         + (ETH_ADDRESS_SIZE as usize * addresses_count) // Address bytes
         + topics_count.map_or(0, |t| t.iter().map(|x| ETH_TOPIC_SIZE as usize * x.len()).sum()); // Topics bytes
 
-  // Estimate response size based on received logs
-  let response_size_bytes = estimate_log_entry_size(logs_received) as u64; // Logs in bytes
+  BASE_CALL_CYCLES + request_size_bytes as u64 * CYCLES_PER_BYTE_SEND
+}
 
-  // Compute cycles for sending request and receiving response
-  let cycles_for_request = request_size_bytes as u64 * CYCLES_PER_BYTE_SEND;
-  let cycles_for_response = response_size_bytes * CYCLES_PER_BYTE_RECEIVE;
-  log_with_metrics!("cycles_for_response actual: {}", cycles_for_response);
+/// Weight of a filter in the query plan: its address and every topic it matches
+pub fn query_share(filter: &Filter) -> u64 {
+  let topics_count: usize = filter
+    .topics
+    .as_ref()
+    .map_or(0, |topics| topics.iter().map(Vec::len).sum());
 
-  // Total cycles usage including base call cost and multiple RPC queries
-  BASE_CALL_CYCLES + cycles_for_request + cycles_for_response + (cycles_for_request + cycles_for_response)
+  1 + topics_count as u64
 }
 
-fn charge_subscribers(addresses_amound: usize, cycles_used: u64) {
+/// Splits `cost` in proportion to `weights`, the shares add up to `cost` exactly.
+fn split_cost(weights: &[u64], cost: u64) -> Vec<u64> {
+  let total_weight: u128 = weights.iter().map(|&weight| weight as u128).sum();
+  if total_weight == 0 {
+    return vec![0; weights.len()];
+  }
+
+  let mut shares: Vec<u64> = weights
+    .iter()
+    .map(|&weight| (cost as u128 * weight as u128 / total_weight) as u64)
+    .collect();
+
+  // rounding leaves less than one cycle per weighted share, hand it out one by one
+  let mut remainder = cost - shares.iter().sum::<u64>();
+  for (share, _) in shares.iter_mut().zip(weights).filter(|(_, weight)| **weight > 0) {
+    if remainder == 0 {
+      break;
+    }
+    *share += 1;
+    remainder -= 1;
+  }
+
+  shares
+}
+
+/// Fetch cost of the chain per subscription fetched for on it, see `query_share`.
+fn fetch_charges<'a>(
+  subscriptions: impl Iterator<Item = &'a SubscriptionInfo>,
+  chain_id: u32,
+  cost: u64,
+) -> Vec<(&'a SubscriptionInfo, u64)> {
+  // subscriptions left out of fetching don't pay for it
+  let subscriptions: Vec<&SubscriptionInfo> = subscriptions
+    .filter(|sub| sub.chain_id == chain_id && sub.is_fetching())
+    .collect();
+  let weights: Vec<u64> = subscriptions.iter().map(|sub| query_share(&sub.filter)).collect();
+
+  subscriptions.into_iter().zip(split_cost(&weights, cost)).collect()
+}
+
+/// Charges the subscriptions of the chain for fetching `from_block..=to_block`, by their share of the query.
+pub fn charge_subscribers(chain_id: u32, from_block: &Nat, to_block: &Nat, addresses_count: usize, cycles_used: u128) {
   let subscriptions = get_state_value!(subscriptions);
+  let charges = fetch_charges(subscriptions.values(), chain_id, 0);
+  let shares: u64 = charges.iter().map(|(sub, _)| query_share(&sub.filter)).sum();
+  STATE.with(|state| {
+    state
+      .borrow_mut()
+      .event_rates
+      .record_fetch_cost(chain_id, cycles_used, shares)
+  });
 
-  // charge subscribers accordingly to amount addresses in their filters
-  let cycles_per_one_address = Nat::from(cycles_used / addresses_amound as u64);
+  let cycles_used = fetch_price(
+    &get_pricing(),
+    chain_id,
+    u64::try_from(cycles_used).unwrap_or(u64::MAX),
+    addresses_count as u64,
+  );
+
+  for (sub_info, cycles) in fetch_charges(subscriptions.values(), chain_id, cycles_used) {
+    if cycles == 0 {
+      continue;
+    }
 
-  for (_sub_id, sub_info) in subscriptions.iter() {
     let subscriber_principal = sub_info.subscriber_principal;
-    match Balances::reduce(&subscriber_principal, cycles_per_one_address.clone()) {
-      Ok(_) => {}
+    let cycles = Nat::from(cycles);
+    match Balances::reduce(&subscriber_principal, cycles.clone()) {
+      Ok(_) => {
+        record_charged(&sub_info.subscription_id, cycles.clone());
+        record_balance_change(
+          subscriber_principal,
+          BalanceChangeKind::FetchCharge,
+          cycles,
+          ChargeReference {
+            subscription_id: Some(sub_info.subscription_id.clone()),
+            chain_id: Some(chain_id),
+            from_block: Some(from_block.clone()),
+            to_block: Some(to_block.clone()),
+          },
+        );
+      }
       Err(BalanceError::BalanceDoesNotExist) => {
         debug_log!(
           "Failed to reduce balance: Balance does not exist for {:?}",
@@ -70,16 +147,20 @@ fn charge_subscribers(addresses_amound: usize, cycles_used: u64) {
   }
 }
 
+/// Fetch logs of all the active filters in `from_block..=to_block`, the caller charges the subscribers of the chain
+/// for them with `charge_subscribers`.
 pub async fn fetch_logs(
   chain_config: &ChainConfig,
   from_block: Nat,
+  to_block: Nat,
   addresses: Option<Vec<Hex20>>,
   topics: Option<Vec<Vec<Hex32>>>,
-) -> Result<Vec<LogEntry>, String> {
+) -> Result<FetchedLogs, String> {
   let addresses = addresses.unwrap_or_default();
+  let to_block_tag = BlockTag::Number(Nat256::try_from(to_block.clone())?);
 
   if addresses.is_empty() {
-    return eth_get_logs_call_with_retry(chain_config, from_block.clone(), None, topics.clone()).await;
+    return eth_get_logs_call_with_retry(chain_config, from_block, to_block_tag, None, topics).await;
   }
 
   let events_per_interval = get_state_value!(estimate_events_num);
@@ -91,9 +172,17 @@ pub async fn fetch_logs(
     let chunk_vec = chunk.to_vec();
     let topics_clone = topics.clone();
     let from_block = from_block.clone();
+    let to_block_tag = to_block_tag.clone();
 
     let fut = async move {
-      eth_get_logs_call_with_retry(chain_config, from_block.clone(), Some(chunk_vec), topics_clone).await
+      eth_get_logs_call_with_retry(
+        chain_config,
+        from_block.clone(),
+        to_block_tag,
+        Some(chunk_vec),
+        topics_clone,
+      )
+      .await
     };
     futures.push(fut);
   }
@@ -101,41 +190,103 @@ pub async fn fetch_logs(
   let results = join_all(futures).await;
 
   let mut merged_logs = Vec::new();
+  let mut total_cycles_used = 0;
   for res in results {
     match res {
-      Ok(logs) => merged_logs.extend(logs),
+      Ok(fetched) => {
+        merged_logs.extend(fetched.logs);
+        total_cycles_used += fetched.cycles_used;
+      }
       Err(e) => return Err(e),
     }
   }
 
-  let total_cycles_used = estimate_cycles_used(&merged_logs, addresses.len(), topics.as_ref());
+  Ok(FetchedLogs {
+    logs: merged_logs,
+    cycles_used: total_cycles_used,
+  })
+}
+
+/// Fetch logs of a single subscription in `from_block..=to_block` (e.g. blocks it missed while paused),
+/// charging only this subscription for it.
+pub async fn fetch_logs_range(
+  chain_config: &ChainConfig,
+  from_block: Nat,
+  to_block: Nat,
+  sub: &SubscriptionInfo,
+) -> Result<Vec<LogEntry>, String> {
+  let filter = &sub.filter;
+
+  let pricing = get_pricing();
+  let estimated_cycles = fetch_price(
+    &pricing,
+    chain_config.chain_id,
+    estimate_fetch_cycles(1, filter.topics.as_ref()),
+    1,
+  );
+  if !Balances::is_sufficient(sub.subscriber_principal, Nat::from(estimated_cycles)).unwrap_or(false) {
+    return Err("Insufficient balance to fetch missed logs".to_string());
+  }
+
+  let fetched = eth_get_logs_call_with_retry(
+    chain_config,
+    from_block.clone(),
+    BlockTag::Number(Nat256::try_from(to_block.clone())?),
+    Some(vec![filter.address.clone()]),
+    filter.topics.clone(),
+  )
+  .await?;
 
-  // After sending request we need to charge cycles for each subscriber accordingly
-  //  to amount of their subscription addresses(filters)
-  // Note: later events_publisher will charge cycles accordingly to amount
-  //  of logs received by each subscriber
-  charge_subscribers(addresses.len(), total_cycles_used);
+  let cycles_used = Nat::from(fetch_price(
+    &pricing,
+    chain_config.chain_id,
+    u64::try_from(fetched.cycles_used).unwrap_or(u64::MAX),
+    1,
+  ));
+  match Balances::reduce(&sub.subscriber_principal, cycles_used.clone()) {
+    Ok(_) => {
+      record_charged(&sub.subscription_id, cycles_used.clone());
+      record_balance_change(
+        sub.subscriber_principal,
+        BalanceChangeKind::FetchCharge,
+        cycles_used,
+        ChargeReference {
+          subscription_id: Some(sub.subscription_id.clone()),
+          chain_id: Some(chain_config.chain_id),
+          from_block: Some(from_block),
+          to_block: Some(to_block),
+        },
+      );
+    }
+    Err(err) => debug_log!(
+      "Failed to reduce balance of {}: {}",
+      sub.subscriber_principal.to_text(),
+      err
+    ),
+  }
 
-  Ok(merged_logs)
+  Ok(fetched.logs)
 }
 
 async fn eth_get_logs_call_with_retry(
   chain_config: &ChainConfig,
   from_block: Nat,
+  to_block: BlockTag,
   addresses: Option<Vec<Hex20>>,
   topics: Option<Vec<Vec<Hex32>>>,
-) -> Result<Vec<LogEntry>, String> {
+) -> Result<FetchedLogs, String> {
   let addresses = addresses.unwrap_or_default();
 
   // Prepare arguments for the RPC call
   let get_logs_args = GetLogsArgs {
     from_block: Some(BlockTag::Number(Nat256::try_from(from_block.clone())?)),
-    to_block: Some(BlockTag::Latest),
+    to_block: Some(to_block),
     addresses,
     topics,
   };
 
   let rpc_config = chain_config.rpc_config.clone();
+  let mut cycles_used = 0;
 
   // Retry logic
   for attempt in 1..=MAX_RETRIES {
@@ -152,11 +303,14 @@ async fn eth_get_logs_call_with_retry(
     )
     .await;
 
+    // the EVM RPC canister returns what it didn't spend on the providers
+    cycles_used += CYCLES_TO_RECEIVE_LOGS.saturating_sub(msg_cycles_refunded128());
+
     match result {
       Ok((result,)) => match result {
         MultiRpcResult::Consistent(r) => {
           return match r {
-            RpcResult::Ok(logs) => Ok(logs),
+            RpcResult::Ok(logs) => Ok(FetchedLogs { logs, cycles_used }),
             RpcResult::Err(err) => Err(format!("GetLogsResult error: {:?}", err)),
           };
         }
@@ -176,3 +330,101 @@ async fn eth_get_logs_call_with_retry(
   }
   Err("Failed to get logs after retries.".to_string())
 }
+
+#[cfg(test)]
+mod tests {
+  use candid::Principal;
+  use evm_logs_types::{PauseMode, SubscriptionStatus};
+
+  use super::*;
+
+  fn create_sub(id: u32, chain_id: u32, topics: Option<Vec<Vec<Hex32>>>) -> SubscriptionInfo {
+    SubscriptionInfo {
+      subscription_id: Nat::from(id),
+      subscriber_principal: Principal::from_slice(&[id as u8]),
+      owner: Principal::from_slice(&[id as u8]),
+      managers: vec![],
+      chain_id,
+      filter: Filter {
+        address: Hex20::from_str("0xd42AcA6E135D1dae6317e776F7EB96Eb91b8eb91").unwrap(),
+        topics,
+        predicate: None,
+        data_masks: None,
+      },
+      stats: vec![],
+      delivery: Default::default(),
+      status: Default::default(),
+      expiry: None,
+      remaining_events: None,
+      memo: None,
+      labels: vec![],
+      callback: None,
+      failure_threshold: DEFAULT_FAILURE_THRESHOLD,
+    }
+  }
+
+  fn topics(count: usize) -> Option<Vec<Vec<Hex32>>> {
+    let topic = Hex32::from_str("0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef").unwrap();
+    Some(vec![vec![topic; count]])
+  }
+
+  fn charges(subscriptions: &[SubscriptionInfo], chain_id: u32, cost: u64) -> Vec<(u32, u64)> {
+    fetch_charges(subscriptions.iter(), chain_id, cost)
+      .into_iter()
+      .map(|(sub, cycles)| (u32::try_from(sub.subscription_id.0.clone()).unwrap(), cycles))
+      .collect()
+  }
+
+  #[test]
+  fn test_split_cost_adds_up_to_cost() {
+    for (weights, cost) in [
+      (vec![1, 1, 1], 100),
+      (vec![3, 1, 5, 2], 1_000_003),
+      (vec![7], 13),
+      (vec![1, 0, 2], 10),
+      (vec![u64::MAX, 1], u64::MAX),
+    ] {
+      let shares = split_cost(&weights, cost);
+      assert_eq!(shares.iter().sum::<u64>(), cost, "weights {:?}", weights);
+    }
+  }
+
+  #[test]
+  fn test_split_cost_is_proportional() {
+    assert_eq!(split_cost(&[1, 3], 400), vec![100, 300]);
+    assert_eq!(split_cost(&[1, 0, 1], 3), vec![2, 0, 1]);
+    assert_eq!(split_cost(&[], 100), Vec::<u64>::new());
+  }
+
+  #[test]
+  fn test_fetch_charges_only_subscriptions_of_the_chain() {
+    let subscriptions = vec![create_sub(1, 1, None), create_sub(2, 10, None), create_sub(3, 1, None)];
+
+    assert_eq!(charges(&subscriptions, 1, 1_000), vec![(1, 500), (3, 500)]);
+    assert_eq!(charges(&subscriptions, 10, 1_000), vec![(2, 1_000)]);
+    assert!(charges(&subscriptions, 5, 1_000).is_empty());
+  }
+
+  #[test]
+  fn test_fetch_charges_by_share_of_addresses_and_topics() {
+    // one address each, plus three topics for the second subscription
+    let subscriptions = vec![create_sub(1, 1, None), create_sub(2, 1, topics(3))];
+
+    let charges = charges(&subscriptions, 1, 1_001);
+
+    assert_eq!(charges, vec![(1, 201), (2, 800)]);
+    assert_eq!(charges.iter().map(|(_, cycles)| cycles).sum::<u64>(), 1_001);
+  }
+
+  #[test]
+  fn test_fetch_charges_skip_subscriptions_not_fetched_for() {
+    let mut paused = create_sub(2, 1, None);
+    paused.status = SubscriptionStatus::Paused {
+      mode: PauseMode::Exclude,
+      at_block: None,
+    };
+    let subscriptions = vec![create_sub(1, 1, None), paused];
+
+    assert_eq!(charges(&subscriptions, 1, 1_000), vec![(1, 1_000)]);
+  }
+}
@@ -3,8 +3,18 @@ use std::{rc::Rc, time::Duration};
 use ic_cdk;
 use ic_cdk_timers::set_timer_interval;
 
-use super::{events_processor::process_and_publish_events, logs_fetcher::fetch_logs, service::ChainService};
-use crate::{internals::misc::get_latest_block_number, log_with_metrics, subscription_manager::queries};
+use super::{
+  events_processor::process_and_publish_events,
+  logs_fetcher::{charge_subscribers, fetch_logs},
+  service::ChainService,
+};
+use crate::{
+  STATE,
+  constants::MAX_RATED_ADDRESSES,
+  internals::misc::get_latest_block_number,
+  log_with_metrics,
+  subscription_manager::{events_publisher::flush_coalesced_events, expiry::expire_subscriptions, queries},
+};
 
 pub fn start_monitoring_internal(service: Rc<ChainService>, interval: Duration) {
   let service_clone = Rc::clone(&service);
@@ -21,6 +31,11 @@ pub fn start_monitoring_internal(service: Rc<ChainService>, interval: Duration)
 
 impl ChainService {
   pub async fn logs_fetching_and_processing_task(&self) {
+    // expired subscriptions are neither fetched for nor charged,
+    // block expiry isn't checked before the first block number is known
+    let last_processed_block = Some(self.last_processed_block.borrow().clone()).filter(|block| *block != 0u32);
+    expire_subscriptions(self.config.chain_id, last_processed_block).await;
+
     let (addresses, topics) = queries::get_active_addresses_and_topics(self.config.chain_id);
 
     if addresses.is_empty() && topics.is_none() {
@@ -36,7 +51,8 @@ impl ChainService {
     if last_processed_block == 0u32 {
       // Initialize last_processed_block
       match get_latest_block_number(self.config.rpc_providers.clone()).await {
-        Ok(latest_block_number) => {
+        Ok(latest_block) => {
+          let latest_block_number = latest_block.number;
           *self.last_processed_block.borrow_mut() = latest_block_number.clone();
           log_with_metrics!(
             "Initialized last block number to {} for Chain ID {:?}",
@@ -57,41 +73,93 @@ impl ChainService {
     }
     let from_block = last_processed_block.clone() + 1u32;
 
+    // the upper bound of the query is resolved up front, so the range fetched and charged for is known exactly,
+    // the subscribers pay for resolving it with the fetch
+    let to_block = match get_latest_block_number(self.config.rpc_providers.clone()).await {
+      Ok(latest_block) => {
+        *self.unbilled_cycles.borrow_mut() += latest_block.cycles_used;
+        latest_block.number
+      }
+      Err(err) => {
+        log_with_metrics!(
+          "Failed to get the latest block number for Chain ID {:?}: {}",
+          self.config.chain_id,
+          err,
+        );
+        return;
+      }
+    };
+
+    if to_block < from_block {
+      log_with_metrics!(
+        "Chain {:?}: No new blocks since {}",
+        self.config.chain_id,
+        last_processed_block
+      );
+      return;
+    }
+
     log_with_metrics!(
-      "Chain {:?}: Fetching logs from block {} to latest",
+      "Chain {:?}: Fetching logs from block {} to {}",
       self.config.chain_id,
-      from_block
+      from_block,
+      to_block
     );
 
-    match fetch_logs(&self.config, from_block.clone(), Some(addresses), topics).await {
-      Ok(logs) => {
-        if !logs.is_empty() {
-          let max_block_number = logs
-            .iter()
-            .filter_map(|log| log.block_number.as_ref())
-            .map(|bn| candid::Nat::from(bn.clone()))
-            .max()
-            .unwrap_or(last_processed_block);
-
-          *self.last_processed_block.borrow_mut() = max_block_number;
-          log_with_metrics!(
-            "Last processed block new value: {}",
-            *self.last_processed_block.borrow()
-          );
+    match fetch_logs(
+      &self.config,
+      from_block.clone(),
+      to_block.clone(),
+      Some(addresses.clone()),
+      topics,
+    )
+    .await
+    {
+      // replaced by a reconfiguration meanwhile, the new service fetches and charges these blocks
+      Ok(_) if !self.is_registered() => {
+        log_with_metrics!(
+          "Chain {:?}: Service replaced while fetching, logs dropped",
+          self.config.chain_id
+        );
+        return;
+      }
+      Ok(fetched) => {
+        // every subscription of the chain pays its share of the addresses and topics of the query,
+        // events_publisher charges for the logs delivered to it later
+        let block_query_cycles = self.unbilled_cycles.take();
+        charge_subscribers(
+          self.config.chain_id,
+          &from_block,
+          &to_block,
+          addresses.len(),
+          block_query_cycles + fetched.cycles_used,
+        );
+
+        let logs = fetched.logs;
+        STATE.with(|state| {
+          state
+            .borrow_mut()
+            .event_rates
+            .record_poll(self.config.chain_id, &addresses, &logs, MAX_RATED_ADDRESSES)
+        });
+
+        *self.last_processed_block.borrow_mut() = to_block.clone();
+        log_with_metrics!(
+          "Last processed block new value: {}",
+          *self.last_processed_block.borrow()
+        );
 
+        if !logs.is_empty() {
           process_and_publish_events(self, logs).await;
-        } else {
-          *self.last_processed_block.borrow_mut() = from_block.clone();
-          log_with_metrics!(
-            "{:?}: No new logs found. Advancing to block {}",
-            self.config.chain_id,
-            from_block
-          );
         }
       }
       Err(e) => {
         log_with_metrics!("Error during logs extraction for {:?}: {}", self.config.chain_id, e);
       }
     }
+
+    let current_block = self.last_processed_block.borrow().clone();
+    flush_coalesced_events(self.config.chain_id, Some(current_block.clone())).await;
+    expire_subscriptions(self.config.chain_id, Some(current_block)).await;
   }
 }
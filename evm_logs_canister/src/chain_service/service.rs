@@ -1,26 +1,30 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{cell::RefCell, rc::Rc, time::Duration};
 
 use candid::Nat;
 use ic_cdk_timers::TimerId;
 
 use super::{config::ChainConfig, monitoring::start_monitoring_internal};
-use crate::log_with_metrics;
+use crate::{CHAIN_SERVICES, log_with_metrics, types::config::Config};
 
 pub struct ChainService {
   pub config: ChainConfig,
   pub last_processed_block: RefCell<Nat>,
   pub timer_id: RefCell<Option<TimerId>>,
+  /// Cycles of latest block queries not charged yet, they're added to the next fetch
+  pub unbilled_cycles: RefCell<u128>,
 }
 
 impl ChainService {
   pub fn new(config: ChainConfig) -> Self {
     let last_processed_block = RefCell::new(Nat::from(0u32));
     let timer_id = RefCell::new(None);
+    let unbilled_cycles = RefCell::new(0);
 
     ChainService {
       config,
       last_processed_block,
       timer_id,
+      unbilled_cycles,
     }
   }
 
@@ -29,6 +33,18 @@ impl ChainService {
     start_monitoring_internal(self, interval);
   }
 
+  /// Whether it's still the running service of its chain, a reconfiguration replaces it
+  pub fn is_registered(&self) -> bool {
+    CHAIN_SERVICES.with(|chain_services| {
+      chain_services
+        .borrow()
+        .iter()
+        .rev() // the latest service of the chain is the running one
+        .find(|service| service.config.chain_id == self.config.chain_id)
+        .is_some_and(|service| std::ptr::eq(service.as_ref(), self))
+    })
+  }
+
   pub fn stop_monitoring(&self) {
     log_with_metrics!("Stopping monitoring for chain ID {}", self.config.chain_id);
     let timer_id = self.timer_id.borrow_mut().take();
@@ -37,3 +53,54 @@ impl ChainService {
     }
   }
 }
+
+/// Replaces the chain services with ones following the new canister config, continuing from the same blocks.
+/// A tick of an old service still in flight neither charges for nor advances over the blocks it fetched,
+/// the new one fetches them again.
+pub fn reconfigure_chain_services(config: &Config) {
+  let services = CHAIN_SERVICES.with(|chain_services| chain_services.borrow().clone());
+
+  let reconfigured: Vec<Rc<ChainService>> = services
+    .iter()
+    .map(|service| {
+      let was_monitoring = service.timer_id.borrow().is_some();
+      if was_monitoring {
+        service.stop_monitoring();
+      }
+
+      let reconfigured = Rc::new(ChainService::new(service.config.with_config(config)));
+      *reconfigured.last_processed_block.borrow_mut() = service.last_processed_block.borrow().clone();
+      *reconfigured.unbilled_cycles.borrow_mut() = service.unbilled_cycles.take();
+
+      if was_monitoring {
+        let interval = Duration::from_secs(reconfigured.config.monitoring_interval_sec);
+        reconfigured.clone().start_monitoring(interval);
+      }
+      reconfigured
+    })
+    .collect();
+
+  CHAIN_SERVICES.with(|chain_services| *chain_services.borrow_mut() = reconfigured);
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::internals::misc::generate_chain_configs;
+
+  #[test]
+  fn test_reconfigured_service_replaces_the_running_one() {
+    let service = Rc::new(ChainService::new(generate_chain_configs()[0].clone()));
+    *service.last_processed_block.borrow_mut() = Nat::from(10u32);
+    CHAIN_SERVICES.with(|chain_services| chain_services.borrow_mut().push(service.clone()));
+    assert!(service.is_registered());
+
+    reconfigure_chain_services(&Config::current());
+
+    // a tick of the old service still in flight leaves the blocks to the new one
+    assert!(!service.is_registered());
+    let reconfigured = CHAIN_SERVICES.with(|chain_services| chain_services.borrow()[0].clone());
+    assert!(reconfigured.is_registered());
+    assert_eq!(*reconfigured.last_processed_block.borrow(), 10u32);
+  }
+}
@@ -23,3 +23,39 @@ pub const EVM_EVENT_SIZE_BYTES: u32 = 800;
 pub const ETH_ADDRESS_SIZE: u32 = 20;
 /// Size of one Ethereum topic
 pub const ETH_TOPIC_SIZE: u32 = 32;
+
+/// Number of most recently published logs remembered to drop duplicates
+pub const SEEN_LOGS_WINDOW: usize = 20_000;
+
+/// Events kept for a subscription paused in `PauseMode::Buffer`
+pub const MAX_PAUSED_EVENTS: usize = 1_000;
+
+/// Keys buffered per coalescing window, the oldest key is dropped first
+pub const MAX_COALESCED_KEYS: usize = 1_000;
+
+/// Subscriptions returned by one page of `query_subscriptions` if no limit is given
+pub const DEFAULT_SUBSCRIPTIONS_PAGE_SIZE: u32 = 100;
+/// Upper bound of the page size of `query_subscriptions`
+pub const MAX_SUBSCRIPTIONS_PAGE_SIZE: u32 = 1_000;
+
+/// Balance ledger entries kept per principal, the oldest ones are dropped first
+pub const MAX_BALANCE_HISTORY_ENTRIES: usize = 1_000;
+/// Entries returned by one page of `get_balance_history` if no limit is given
+pub const DEFAULT_BALANCE_HISTORY_PAGE_SIZE: u32 = 100;
+
+/// Addresses whose event rates are kept for quotes, the least recently polled ones are dropped first
+pub const MAX_RATED_ADDRESSES: usize = 10_000;
+
+/// Limits of the metadata echoed in every notification of a subscription
+pub const MAX_MEMO_SIZE: usize = 256;
+pub const MAX_LABELS: usize = 16;
+pub const MAX_LABEL_SIZE: usize = 128;
+
+/// Longest method name accepted as a notification callback
+pub const MAX_CALLBACK_METHOD_SIZE: usize = 64;
+
+/// Consecutive delivery failures after which a subscription is suspended, if not set on registration
+pub const DEFAULT_FAILURE_THRESHOLD: u32 = 10;
+
+/// Time to top up the balance before a subscription suspended for low balance is cancelled
+pub const LOW_BALANCE_GRACE_PERIOD_SECS: u64 = 3 * 24 * 60 * 60;
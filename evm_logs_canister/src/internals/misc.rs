@@ -2,15 +2,16 @@ use std::cell::RefCell;
 
 use candid::Nat;
 use evm_rpc_types::{Block, BlockTag, ConsensusStrategy, MultiRpcResult, RpcApi, RpcConfig, RpcResult, RpcServices};
-use ic_cdk::api::{call::call_with_payment128, time};
+use ic_cdk::api::{
+  call::{call_with_payment128, msg_cycles_refunded128},
+  time,
+};
 
 use crate::{
   chain_service::ChainConfig,
-  constants::{
-    ARBITRUM_CHAIN_ID, BASE_CHAIN_ID, BSC_CHAIN_ID, ETHEREUM_CHAIN_ID, EVM_EVENT_SIZE_BYTES, OPTIMISM_CHAIN_ID,
-    POLYGON_CHAIN_ID,
-  },
+  constants::{ARBITRUM_CHAIN_ID, BASE_CHAIN_ID, BSC_CHAIN_ID, ETHEREUM_CHAIN_ID, OPTIMISM_CHAIN_ID, POLYGON_CHAIN_ID},
   get_state_value,
+  types::config::Config,
 };
 
 thread_local! {
@@ -27,7 +28,13 @@ pub fn timestamp_millis() -> u64 {
   timestamp_nanos() / 1_000_000
 }
 
-pub async fn get_latest_block_number(rpc_providers: RpcServices) -> Result<Nat, String> {
+/// Latest block number of a chain and the cycles the EVM RPC canister kept for the query
+pub struct LatestBlock {
+  pub number: Nat,
+  pub cycles_used: u128,
+}
+
+pub async fn get_latest_block_number(rpc_providers: RpcServices) -> Result<LatestBlock, String> {
   let cycles = 10_000_000_000; // TODO
 
   let block_tag = BlockTag::Latest;
@@ -38,21 +45,25 @@ pub async fn get_latest_block_number(rpc_providers: RpcServices) -> Result<Nat,
   };
   let evm_rpc_canister = get_state_value!(evm_rpc_canister);
 
-  let (result,): (MultiRpcResult<Block>,) = call_with_payment128(
+  let result: Result<(MultiRpcResult<Block>,), _> = call_with_payment128(
     evm_rpc_canister,
     "eth_getBlockByNumber",
     (rpc_providers, rpc_config, block_tag),
     cycles,
   )
-  .await
-  .map_err(|e| format!("Call failed: {:?}", e))?;
+  .await;
+  // the EVM RPC canister returns what it didn't spend on the providers
+  let cycles_used = cycles.saturating_sub(msg_cycles_refunded128());
+  let (result,) = result.map_err(|e| format!("Call failed: {:?}", e))?;
 
   match result {
     MultiRpcResult::Consistent(res) => match res {
       RpcResult::Ok(block) => {
         let block_number = block.number;
-        let block_number: Nat = block_number.into();
-        Ok(block_number)
+        Ok(LatestBlock {
+          number: block_number.into(),
+          cycles_used,
+        })
       }
       RpcResult::Err(err) => Err(format!("RPC error: {:?}", err)),
     },
@@ -62,8 +73,7 @@ pub async fn get_latest_block_number(rpc_providers: RpcServices) -> Result<Nat,
 
 pub fn generate_chain_configs() -> Vec<ChainConfig> {
   let evm_rpc_canister = get_state_value!(evm_rpc_canister);
-  let estimate_events_num = get_state_value!(estimate_events_num);
-  let response_size_estimate = (estimate_events_num * EVM_EVENT_SIZE_BYTES) as u64;
+  let response_size_estimate = Config::current().response_size_estimate();
 
   vec![
     ChainConfig {
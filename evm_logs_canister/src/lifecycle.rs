@@ -1,4 +1,5 @@
 mod canister_geek;
+mod guards;
 mod init;
 mod methods;
 mod migration;
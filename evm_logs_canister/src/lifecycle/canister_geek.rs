@@ -4,12 +4,14 @@ use ic_utils::{
   get_information, update_information,
 };
 
+use super::guards::caller_is_operator;
+
 #[query(name = "getCanistergeekInformation")]
 pub async fn get_canistergeek_information(request: GetInformationRequest) -> GetInformationResponse<'static> {
   get_information(request)
 }
 
-#[update(name = "updateCanistergeekInformation")]
+#[update(name = "updateCanistergeekInformation", guard = "caller_is_operator")]
 pub async fn update_canistergeek_information(request: UpdateInformationRequest) {
   update_information(request);
 }
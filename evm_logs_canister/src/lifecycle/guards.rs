@@ -0,0 +1,30 @@
+use evm_logs_types::Role;
+use ic_cdk::{api::is_controller, caller};
+
+use crate::STATE;
+
+pub fn caller_is_controller() -> Result<(), String> {
+  if is_controller(&caller()) {
+    Ok(())
+  } else {
+    Err("Only controllers can call this method".to_string())
+  }
+}
+
+pub fn caller_is_operator() -> Result<(), String> {
+  caller_has_role(Role::Operator)
+}
+
+pub fn caller_is_publisher() -> Result<(), String> {
+  caller_has_role(Role::Publisher)
+}
+
+/// Controllers hold every role
+fn caller_has_role(role: Role) -> Result<(), String> {
+  let caller = caller();
+  if is_controller(&caller) || STATE.with(|state| state.borrow().roles.has(&caller, role)) {
+    Ok(())
+  } else {
+    Err(format!("Caller {} isn't granted the {:?} role", caller, role))
+  }
+}
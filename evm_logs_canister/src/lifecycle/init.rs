@@ -1,10 +1,28 @@
+use evm_logs_types::ConfigUpdate;
 use ic_cdk_macros::init;
 
-use crate::{log_with_metrics, subscription_manager, types};
+use crate::{
+  chain_service::service::reconfigure_chain_services,
+  log_with_metrics, subscription_manager,
+  types::{self, config::Config},
+};
 
 #[init]
 async fn init(config: types::config::Config) {
+  // like an invalid config update, an invalid config fails the install
+  config.validate().expect("Invalid config");
   subscription_manager::subscription::init();
   types::state::init(config);
   log_with_metrics!("EVM logs canister initialized.");
 }
+
+/// Validates and stores the updated config, running chain services pick it up immediately.
+pub fn update_config(update: ConfigUpdate) -> Result<(), String> {
+  let config = Config::current().updated(update)?;
+
+  types::state::init(config.clone());
+  reconfigure_chain_services(&config);
+
+  log_with_metrics!("Config updated: {:?}", config);
+  Ok(())
+}
@@ -1,35 +1,101 @@
 use candid::{Nat, Principal, candid_method};
 use evm_logs_types::*;
-use ic_cdk::caller;
+use ic_cdk::{
+  api::call::{msg_cycles_accept128, msg_cycles_available128},
+  caller,
+};
 use ic_cdk_macros::*;
 
-use crate::{log_with_metrics, subscription_manager, types::balances::Balances};
+use super::guards::{caller_is_controller, caller_is_operator, caller_is_publisher};
+use crate::{
+  STATE, log_with_metrics, subscription_manager,
+  types::{
+    balance_ledger::{self, ChargeReference, record_balance_change},
+    balances::Balances,
+    pricing,
+  },
+};
 
 /// Register subscription by specified filter (addresses and topics)
 #[update(name = "subscribe")]
 #[candid_method(update)]
 pub async fn subscribe(registration: SubscriptionRegistration) -> RegisterSubscriptionResult {
-  let received_cycles = ic_cdk::api::call::msg_cycles_available();
+  let canister_to_top_up = registration.canister_to_top_up;
+
+  // a rejected registration credits nobody, the attached cycles are refunded to the caller
+  let result = subscription_manager::subscription::register_subscription(caller(), registration).await;
+  if let RegisterSubscriptionResult::Ok(_) = result
+    && let Err(err) = accept_top_up(canister_to_top_up)
+  {
+    log_with_metrics!("Failed to top up balance: {}", err);
+  }
+
+  result
+}
+
+/// Accepts the cycles attached to the call and credits them to the balance of `canister_to_top_up`.
+fn accept_top_up(canister_to_top_up: Principal) -> Result<(), String> {
+  let received_cycles = msg_cycles_accept128(msg_cycles_available128());
 
   log_with_metrics!(
     "Received cycles: {:?}, for principal: {:?}",
     received_cycles,
-    registration.canister_to_top_up.to_text()
+    canister_to_top_up.to_text()
   );
 
-  if let Err(err) = Balances::top_up(registration.canister_to_top_up, Nat::from(received_cycles)) {
-    log_with_metrics!("Failed to top up balance: {}", err);
-    return RegisterSubscriptionResult::Err(RegisterSubscriptionError::InsufficientFunds);
-  }
+  Balances::top_up(canister_to_top_up, Nat::from(received_cycles))?;
+  record_balance_change(
+    canister_to_top_up,
+    BalanceChangeKind::TopUp,
+    Nat::from(received_cycles),
+    ChargeReference::default(),
+  );
+  subscription_manager::subscription::restore_funded_subscriptions(canister_to_top_up);
 
-  subscription_manager::subscription::register_subscription(registration).await
+  Ok(())
 }
 
-/// Unsubscribe from subscription with specified ID
+/// Unsubscribe from subscription with specified ID,
+/// with `refund` the remaining balance goes back to the subscriber after its last subscription
 #[update(name = "unsubscribe")]
 #[candid_method(update)]
-pub async fn unsubscribe(subscription_id: Nat) -> UnsubscribeResult {
-  subscription_manager::subscription::unsubscribe(caller(), subscription_id)
+pub async fn unsubscribe(subscription_id: Nat, refund: Option<bool>) -> UnsubscribeResult {
+  subscription_manager::subscription::cancel_subscription(caller(), subscription_id, refund.unwrap_or(false)).await
+}
+
+/// Replace the filter of the subscription keeping its ID and balance
+#[update(name = "update_subscription")]
+#[candid_method(update)]
+pub fn update_subscription(subscription_id: Nat, filter: Filter) -> SubscriptionResult {
+  subscription_manager::subscription::update_filter(caller(), subscription_id, filter)
+}
+
+/// Suspend delivery of the subscription, see `PauseMode`
+#[update(name = "pause_subscription")]
+#[candid_method(update)]
+pub fn pause_subscription(subscription_id: Nat, mode: PauseMode) -> SubscriptionResult {
+  subscription_manager::subscription::pause_subscription(caller(), subscription_id, mode)
+}
+
+/// Continue delivery of a paused subscription from the block it was paused at
+#[update(name = "resume_subscription")]
+#[candid_method(update)]
+pub async fn resume_subscription(subscription_id: Nat) -> SubscriptionResult {
+  subscription_manager::subscription::resume_subscription(caller(), subscription_id).await
+}
+
+/// Bring back a subscription suspended after repeated delivery failures
+#[update(name = "reactivate_subscription")]
+#[candid_method(update)]
+pub fn reactivate_subscription(subscription_id: Nat) -> SubscriptionResult {
+  subscription_manager::subscription::reactivate_subscription(caller(), subscription_id)
+}
+
+/// Replace delegated managers of the subscription, allowed only for its owner
+#[update(name = "set_subscription_managers")]
+#[candid_method(update)]
+pub fn set_subscription_managers(subscription_id: Nat, managers: Vec<Principal>) -> SubscriptionResult {
+  subscription_manager::subscription::set_managers(caller(), subscription_id, managers)
 }
 
 /// Get all subscriptions assigned to the user (takes caller as a parameter implicitly)
@@ -39,6 +105,13 @@ pub fn get_user_subscriptions() -> Vec<SubscriptionInfo> {
   subscription_manager::queries::get_user_subscriptions(caller())
 }
 
+/// Get delivery statistics of the subscription
+#[query(name = "get_subscription_stats")]
+#[candid_method(query)]
+pub fn get_subscription_stats(subscription_id: Nat) -> Option<SubscriptionStats> {
+  subscription_manager::stats::get_stats(&subscription_id)
+}
+
 /// Get all evm-logs-canister filters info [generally for testing purpose]
 #[query(name = "get_active_filters")]
 #[candid_method(query)]
@@ -46,7 +119,8 @@ pub fn get_active_filters() -> Vec<evm_logs_types::Filter> {
   subscription_manager::queries::get_active_filters()
 }
 
-// Get all evm-logs-canister subscriptions info
+// Get evm-logs-canister subscriptions info after `from_id`, one page of `MAX_SUBSCRIPTIONS_PAGE_SIZE` at most
+// (see `query_subscriptions`)
 #[query(name = "get_subscriptions")]
 #[candid_method(query)]
 pub fn get_subscriptions(
@@ -57,20 +131,26 @@ pub fn get_subscriptions(
   subscription_manager::queries::get_subscriptions_info(namespace, from_id, filters)
 }
 
+/// Get one page of subscriptions matching the query, ordered by ID
+#[query(name = "query_subscriptions")]
+#[candid_method(query)]
+pub fn query_subscriptions(query: SubscriptionsQuery) -> SubscriptionsPage {
+  subscription_manager::queries::query_subscriptions(query)
+}
+
+/// Estimate the daily cost of subscribing to the filter on the chain
+#[query(name = "quote_subscription")]
+#[candid_method(query)]
+pub fn quote_subscription(chain_id: u32, filter: Filter) -> QuoteSubscriptionResult {
+  subscription_manager::quotes::quote_subscription(chain_id, filter)
+}
+
 /// Top up balance of specific user that is subscribed on some events
 #[update(name = "top_up_balance")]
 #[candid_method(update)]
 pub fn top_up_balance(canister_to_top_up: Principal) -> TopUpBalanceResult {
-  let received_cycles = ic_cdk::api::call::msg_cycles_available();
-
-  log_with_metrics!(
-    "Received cycles: {:?}, for principal: {:?}",
-    received_cycles,
-    canister_to_top_up.to_text()
-  );
-
-  match Balances::top_up(canister_to_top_up, Nat::from(received_cycles)) {
-    Ok(_) => TopUpBalanceResult::Ok,
+  match accept_top_up(canister_to_top_up) {
+    Ok(()) => TopUpBalanceResult::Ok,
     Err(err) => {
       log_with_metrics!("Failed to top up balance: {}", err);
       TopUpBalanceResult::Err(TopUpBalanceError::GenericError)
@@ -78,6 +158,34 @@ pub fn top_up_balance(canister_to_top_up: Principal) -> TopUpBalanceResult {
   }
 }
 
+/// Top up balance of the user with tokens of a payment ledger the caller approved this canister to spend
+#[update(name = "deposit")]
+#[candid_method(update)]
+pub async fn deposit(ledger: Principal, amount: Nat, canister_to_top_up: Principal) -> DepositResult {
+  subscription_manager::deposits::deposit(caller(), ledger, amount, canister_to_top_up).await
+}
+
+/// Get ledgers accepted by `deposit` and their rates
+#[query(name = "get_payment_ledgers")]
+#[candid_method(query)]
+pub fn get_payment_ledgers() -> Vec<PaymentLedger> {
+  subscription_manager::deposits::get_payment_ledgers()
+}
+
+/// Accept deposits from the ledger at the given rate
+#[update(name = "set_payment_ledger", guard = "caller_is_controller")]
+#[candid_method(update)]
+pub fn set_payment_ledger(payment_ledger: PaymentLedger) -> AdminResult {
+  subscription_manager::deposits::set_payment_ledger(payment_ledger)
+}
+
+/// Stop accepting deposits from the ledger
+#[update(name = "remove_payment_ledger", guard = "caller_is_controller")]
+#[candid_method(update)]
+pub fn remove_payment_ledger(ledger: Principal) -> AdminResult {
+  subscription_manager::deposits::remove_payment_ledger(ledger)
+}
+
 /// Get balance of the specified user by its principal
 #[query(name = "get_balance")]
 #[candid_method(query)]
@@ -86,9 +194,79 @@ pub fn get_balance(canister_id: Principal) -> Nat {
   Balances::get_balance(&canister_id).unwrap()
 }
 
-/// Method for sending events to the Broadcaster [Created only testing purpose]
+/// Deposit cycles of the caller's balance into the destination canister
+#[update(name = "withdraw_balance")]
+#[candid_method(update)]
+pub async fn withdraw_balance(amount: Nat, destination_canister: Principal) -> WithdrawBalanceResult {
+  subscription_manager::withdrawals::withdraw(caller(), amount, destination_canister).await
+}
+
+/// Get changes of the balance of the specified user after `from_id`, ordered by ID
+#[query(name = "get_balance_history")]
+#[candid_method(query)]
+pub fn get_balance_history(canister_id: Principal, from_id: Option<u64>, limit: Option<u32>) -> BalanceHistoryPage {
+  balance_ledger::get_balance_history(&canister_id, from_id, limit)
+}
+
+/// Get the current pricing schedule
+#[query(name = "get_pricing")]
+#[candid_method(query)]
+pub fn get_pricing() -> PricingSchedule {
+  pricing::get_pricing()
+}
+
+/// Replace the pricing schedule
+#[update(name = "set_pricing", guard = "caller_is_controller")]
+#[candid_method(update)]
+pub fn set_pricing(schedule: PricingSchedule) -> AdminResult {
+  pricing::set_pricing(schedule)
+}
+
+/// Apply changes to the canister config, fields left out keep their value
+#[update(name = "update_config", guard = "caller_is_controller")]
+#[candid_method(update)]
+pub fn update_config(update: ConfigUpdate) -> AdminResult {
+  match super::init::update_config(update) {
+    Ok(()) => AdminResult::Ok,
+    Err(err) => AdminResult::Err(AdminError::ImproperConfig(err)),
+  }
+}
+
+/// Grant the role to the principal
+#[update(name = "grant_role", guard = "caller_is_controller")]
+#[candid_method(update)]
+pub fn grant_role(principal: Principal, role: Role) -> AdminResult {
+  STATE.with(|state| state.borrow_mut().roles.grant(principal, role));
+  log_with_metrics!("Role {:?} granted to {}", role, principal.to_text());
+  AdminResult::Ok
+}
+
+/// Revoke the role from the principal
+#[update(name = "revoke_role", guard = "caller_is_controller")]
+#[candid_method(update)]
+pub fn revoke_role(principal: Principal, role: Role) -> AdminResult {
+  if !STATE.with(|state| state.borrow_mut().roles.revoke(&principal, role)) {
+    return AdminResult::Err(AdminError::ImproperConfig(format!(
+      "{} doesn't have the {:?} role",
+      principal.to_text(),
+      role
+    )));
+  }
+
+  log_with_metrics!("Role {:?} revoked from {}", role, principal.to_text());
+  AdminResult::Ok
+}
+
+/// Get principals granted the role, controllers aren't listed
+#[query(name = "get_role_members", guard = "caller_is_operator")]
+#[candid_method(query)]
+pub fn get_role_members(role: Role) -> Vec<Principal> {
+  STATE.with(|state| state.borrow().roles.members(role))
+}
+
+/// Method for sending events to the Broadcaster, allowed only for publishers.
 /// Used IRC72 proposal.
-#[update(name = "publish_events")]
+#[update(name = "publish_events", guard = "caller_is_publisher")]
 #[candid_method(update)]
 pub async fn icrc72_publish(events: Vec<Event>) {
   subscription_manager::events_publisher::publish_events(events).await
@@ -1,17 +1,24 @@
 use std::{rc::Rc, time::Duration};
 
-use candid::Nat;
+use candid::{Nat, de::IDLDeserialize, utils::ArgumentDecoder};
 use canister_utils::debug_log;
-use ic_cdk::storage;
+use evm_logs_types::ConfigUpdate;
+use ic_cdk::{api::stable, storage};
 
+use super::init::update_config;
 use crate::{
   CHAIN_SERVICES, FILTERS_MANAGER, NEXT_NOTIFICATION_ID, NEXT_SUBSCRIPTION_ID, STATE,
   chain_service::{ChainConfig, service::ChainService},
   internals::misc::DEFAULT_MONITORING_TIME,
   log_filters::filter_manager::FilterManager,
-  types::state::State,
+  types::{state::State, state_v0::StateV0},
 };
 
+/// Saved by `pre_upgrade`
+type StableLayout = (State, Nat, Nat, FilterManager, Vec<ChainConfig>);
+/// Saved by canisters deployed before the layout changed, see `StateV0`
+type StableLayoutV0 = (StateV0, Nat, Nat, FilterManager, Vec<ChainConfig>);
+
 #[ic_cdk::pre_upgrade]
 fn pre_upgrade() {
   let state = STATE.with(|state| state.borrow().clone());
@@ -40,15 +47,9 @@ fn pre_upgrade() {
 }
 
 #[ic_cdk::post_upgrade]
-fn post_upgrade() {
-  let (
-        saved_state,
-        saved_next_subscription_id,
-        saved_next_notification_id,
-        saved_topics_manager,
-        saved_chain_configs,
-    ): (State, Nat, Nat, FilterManager, Vec<ChainConfig>) =
-        storage::stable_restore().expect("Failed to restore state after upgrade");
+fn post_upgrade(config_update: Option<ConfigUpdate>) {
+  let (saved_state, saved_next_subscription_id, saved_next_notification_id, saved_topics_manager, saved_chain_configs) =
+    decode_stable_layout(&stable::stable_bytes()).expect("Failed to restore state after upgrade");
 
   STATE.with(|state| {
     *state.borrow_mut() = saved_state;
@@ -81,5 +82,245 @@ fn post_upgrade() {
     *chain_services.borrow_mut() = restored_services;
   });
 
+  // an invalid config fails the upgrade, keeping the previous version
+  if let Some(config_update) = config_update {
+    update_config(config_update).expect("Invalid config update");
+  }
+
   debug_log!("post_upgrade: State restored successfully.");
 }
+
+/// Decodes the current layout, falling back to migrating the v0 one.
+fn decode_stable_layout(bytes: &[u8]) -> Result<StableLayout, String> {
+  decode_layout::<StableLayout>(bytes).or_else(|err| {
+    let (state, next_subscription_id, next_notification_id, filters_manager, chain_configs) =
+      decode_layout::<StableLayoutV0>(bytes).map_err(|v0_err| format!("{err}, as v0 layout: {v0_err}"))?;
+
+    Ok((
+      state.into(),
+      next_subscription_id,
+      next_notification_id,
+      filters_manager,
+      chain_configs,
+    ))
+  })
+}
+
+/// Same as `storage::stable_restore`, for bytes read once.
+fn decode_layout<T>(bytes: &[u8]) -> Result<T, String>
+where
+  T: for<'de> ArgumentDecoder<'de>,
+{
+  let mut de = IDLDeserialize::new(bytes).map_err(|err| format!("{:?}", err))?;
+  ArgumentDecoder::decode(&mut de).map_err(|err| format!("{:?}", err))
+}
+
+#[cfg(test)]
+mod tests {
+  use std::str::FromStr;
+
+  use candid::Principal;
+  use evm_logs_types::{
+    BalanceChangeKind, BalanceLedgerEntry, ChainMultiplier, DeliveryMode, Filter, PaymentLedger, PricingSchedule, Role,
+    SubscriptionStats,
+  };
+  use evm_rpc_types::Hex20;
+
+  use super::*;
+
+  const ADDR_HEX20: &str = "0xd42AcA6E135D1dae6317e776F7EB96Eb91b8eb91";
+
+  fn subscriber() -> Principal {
+    Principal::from_slice(&[1])
+  }
+
+  /// Saved by `pre_upgrade` of the last release with the v0 layout: subscription 7 of `subscriber()` on chain 1
+  /// with a 1000 cycles balance, the next subscription id 8, the next notification id 3 and every chain monitored.
+  const V0_LAYOUT: &[u8] = include_bytes!("../../tests/fixtures/stable_layout_v0.bin");
+
+  fn migrated_state() -> State {
+    decode_stable_layout(V0_LAYOUT).unwrap().0
+  }
+
+  /// Saves and restores the state the way an upgrade does
+  fn upgraded(state: State) -> State {
+    let bytes = candid::encode_args((
+      state,
+      Nat::from(1u32),
+      Nat::from(1u32),
+      FilterManager::default(),
+      Vec::<ChainConfig>::new(),
+    ))
+    .unwrap();
+
+    decode_stable_layout(&bytes).unwrap().0
+  }
+
+  #[test]
+  fn test_v0_layout_is_migrated() {
+    assert!(decode_layout::<StableLayout>(V0_LAYOUT).is_err());
+
+    let (state, next_subscription_id, next_notification_id, filters_manager, chain_configs) =
+      decode_stable_layout(V0_LAYOUT).unwrap();
+
+    assert_eq!(next_subscription_id, Nat::from(8u32));
+    assert_eq!(next_notification_id, Nat::from(3u32));
+    assert_eq!(state.proxy_canister, Principal::from_slice(&[3]));
+    assert_eq!(state.subscribers[&subscriber()], vec![Nat::from(7u32)]);
+    assert_eq!(state.user_balances.balances[&subscriber()], Nat::from(1_000u32));
+    assert!(state.subscriptions[&Nat::from(7u32)].filter.topics.is_some());
+    assert_eq!(filters_manager.get_active_addresses_and_topics(1).0.len(), 1);
+    assert!(chain_configs.iter().any(|config| config.chain_id == 1));
+  }
+
+  #[test]
+  fn test_v0_subscriptions_deliver_immediately() {
+    let state = migrated_state();
+
+    assert_eq!(state.subscriptions[&Nat::from(7u32)].delivery, DeliveryMode::Immediate);
+    assert!(state.coalesce_buffers.is_empty());
+  }
+
+  #[test]
+  fn test_seen_logs_survive_upgrades() {
+    let key = (1, vec![1u8; 32], vec![2u8; 32], Nat::from(0u32));
+    assert!(migrated_state().seen_logs.insert(key.clone(), 10));
+
+    let mut state = State::default();
+    state.seen_logs.insert(key.clone(), 10);
+
+    assert!(!upgraded(state).seen_logs.insert(key, 10));
+  }
+
+  #[test]
+  fn test_v0_subscriptions_are_owned_by_their_subscriber() {
+    let subscription = &migrated_state().subscriptions[&Nat::from(7u32)];
+
+    assert_eq!(subscription.owner, subscriber());
+    assert!(subscription.managers.is_empty());
+  }
+
+  #[test]
+  fn test_v0_subscriptions_are_active() {
+    let state = migrated_state();
+
+    assert!(state.subscriptions[&Nat::from(7u32)].is_active());
+    assert!(state.paused_buffers.is_empty());
+  }
+
+  #[test]
+  fn test_subscription_stats_survive_upgrades() {
+    assert!(migrated_state().subscription_stats.is_empty());
+
+    let stats = SubscriptionStats {
+      events_delivered: 3,
+      ..Default::default()
+    };
+    let mut state = State::default();
+    state.subscription_stats.insert(Nat::from(7u32), stats.clone());
+
+    assert_eq!(upgraded(state).subscription_stats[&Nat::from(7u32)], stats);
+  }
+
+  #[test]
+  fn test_balance_ledger_survives_upgrades() {
+    let entry = BalanceLedgerEntry {
+      id: 0,
+      timestamp: 0,
+      kind: BalanceChangeKind::TopUp,
+      amount: Nat::from(5u32),
+      balance_after: Nat::from(5u32),
+      subscription_id: None,
+      chain_id: None,
+      from_block: None,
+      to_block: None,
+    };
+    assert!(
+      migrated_state()
+        .balance_ledger
+        .page(&subscriber(), None, 10)
+        .entries
+        .is_empty()
+    );
+
+    let mut state = State::default();
+    state.balance_ledger.append(subscriber(), entry.clone(), 10);
+
+    let mut ledger = upgraded(state).balance_ledger;
+    assert_eq!(ledger.page(&subscriber(), None, 10).entries.len(), 1);
+    // ids keep increasing after the upgrade
+    assert_eq!(ledger.append(subscriber(), entry, 10), 1);
+  }
+
+  #[test]
+  fn test_pricing_survives_upgrades() {
+    assert_eq!(migrated_state().pricing, PricingSchedule::default());
+
+    let pricing = PricingSchedule {
+      delivery_fee: 10,
+      polling_fee: 20,
+      chain_multipliers: vec![ChainMultiplier {
+        chain_id: 1,
+        percent: 150,
+      }],
+      margin_percent: 5,
+    };
+    let state = State {
+      pricing: pricing.clone(),
+      ..State::default()
+    };
+
+    assert_eq!(upgraded(state).pricing, pricing);
+  }
+
+  #[test]
+  fn test_event_rates_survive_upgrades() {
+    let address = Hex20::from_str(ADDR_HEX20).unwrap();
+    let filter = Filter {
+      address: address.clone(),
+      topics: None,
+      predicate: None,
+      data_masks: None,
+    };
+    assert_eq!(migrated_state().event_rates.observed_events(1, &filter), (0, 0));
+
+    let mut state = State::default();
+    state.event_rates.record_poll(1, &[address], &[], 10);
+    state.event_rates.record_fetch_cost(1, 1_000, 2);
+
+    let event_rates = upgraded(state).event_rates;
+    assert_eq!(event_rates.observed_events(1, &filter), (0, 1));
+    assert_eq!(event_rates.fetch_cost_per_share(1), Some(500));
+  }
+
+  #[test]
+  fn test_payment_ledgers_survive_upgrades() {
+    assert!(migrated_state().payment_ledgers.is_empty());
+
+    let ledger = PaymentLedger {
+      ledger: Principal::from_slice(&[4]),
+      token_symbol: "ckUSDC".to_string(),
+      cycles_numerator: Nat::from(3u32),
+      cycles_denominator: Nat::from(2u32),
+    };
+    let state = State {
+      payment_ledgers: vec![ledger.clone()],
+      ..State::default()
+    };
+
+    assert_eq!(upgraded(state).payment_ledgers, vec![ledger]);
+  }
+
+  #[test]
+  fn test_roles_survive_upgrades() {
+    let publisher = Principal::from_slice(&[5]);
+    assert!(!migrated_state().roles.has(&publisher, Role::Publisher));
+
+    let mut state = State::default();
+    state.roles.grant(publisher, Role::Publisher);
+
+    let roles = upgraded(state).roles;
+    assert!(roles.has(&publisher, Role::Publisher));
+    assert!(!roles.has(&publisher, Role::Operator));
+  }
+}
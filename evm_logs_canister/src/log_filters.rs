@@ -1 +1,3 @@
+pub mod data_mask;
 pub mod filter_manager;
+pub mod predicate;
@@ -0,0 +1,152 @@
+use evm_logs_types::DataMask;
+
+/// Largest `data` offset a mask may reach, anything beyond is rejected at registration
+pub const MAX_DATA_MASK_END: usize = 32 * 1024;
+/// Longest byte sequence a single mask may compare
+pub const MAX_DATA_MASK_LEN: usize = 256;
+/// Maximum number of masks in one filter
+pub const MAX_DATA_MASKS: usize = 16;
+
+/// Checks all masks against the log data. If the data is too short to cover a mask, the mask
+/// does not match, regardless of `negate`.
+pub fn data_matches_masks(data: &[u8], masks: &[DataMask]) -> bool {
+  masks.iter().all(|mask| data_matches_mask(data, mask))
+}
+
+fn data_matches_mask(data: &[u8], data_mask: &DataMask) -> bool {
+  let start = data_mask.offset as usize;
+  let Some(bytes) = start
+    .checked_add(data_mask.mask.len())
+    .and_then(|end| data.get(start..end))
+  else {
+    return false;
+  };
+
+  let equal = bytes
+    .iter()
+    .zip(&data_mask.mask)
+    .zip(&data_mask.value)
+    .all(|((byte, mask), value)| byte & mask == value & mask);
+
+  equal != data_mask.negate
+}
+
+pub fn validate_data_masks(masks: &[DataMask]) -> Result<(), String> {
+  if masks.len() > MAX_DATA_MASKS {
+    return Err(format!("At most {} data masks are allowed", MAX_DATA_MASKS));
+  }
+
+  for (i, data_mask) in masks.iter().enumerate() {
+    let len = data_mask.mask.len();
+    if len == 0 || len > MAX_DATA_MASK_LEN {
+      return Err(format!("Data mask #{} must be 1..={} bytes long", i, MAX_DATA_MASK_LEN));
+    }
+    if data_mask.value.len() != len {
+      return Err(format!("Data mask #{} value and mask lengths differ", i));
+    }
+    if (data_mask.offset as usize)
+      .checked_add(len)
+      .is_none_or(|end| end > MAX_DATA_MASK_END)
+    {
+      return Err(format!(
+        "Data mask #{} is out of range: offset {} + {} bytes exceeds {}",
+        i, data_mask.offset, len, MAX_DATA_MASK_END
+      ));
+    }
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn data_mask(offset: u32, mask: Vec<u8>, value: Vec<u8>, negate: bool) -> DataMask {
+    DataMask {
+      offset,
+      mask,
+      value,
+      negate,
+    }
+  }
+
+  fn sample_data() -> Vec<u8> {
+    let mut data = vec![0xabu8; 32];
+    data.extend(vec![0u8; 32]);
+    data.extend([vec![0u8; 31], vec![7u8]].concat());
+    data
+  }
+
+  #[test]
+  fn test_first_word_equals() {
+    let data = sample_data();
+
+    assert!(data_matches_masks(
+      &data,
+      &[data_mask(0, vec![0xff; 32], vec![0xab; 32], false)]
+    ));
+    assert!(!data_matches_masks(
+      &data,
+      &[data_mask(0, vec![0xff; 32], vec![0xac; 32], false)]
+    ));
+  }
+
+  #[test]
+  fn test_word_non_zero() {
+    let data = sample_data();
+    let non_zero_at = |offset| data_mask(offset, vec![0xff; 32], vec![0; 32], true);
+
+    assert!(data_matches_masks(&data, &[non_zero_at(64)]));
+    assert!(!data_matches_masks(&data, &[non_zero_at(32)]));
+  }
+
+  #[test]
+  fn test_partial_mask_ignores_unmasked_bits() {
+    let data = sample_data();
+
+    assert!(data_matches_masks(
+      &data,
+      &[data_mask(95, vec![0x06], vec![0x06], false)]
+    ));
+    assert!(!data_matches_masks(
+      &data,
+      &[data_mask(95, vec![0x08], vec![0x08], false)]
+    ));
+  }
+
+  #[test]
+  fn test_short_data_does_not_match() {
+    let data = sample_data();
+
+    assert!(!data_matches_masks(
+      &data,
+      &[data_mask(90, vec![0xff; 8], vec![0; 8], false)]
+    ));
+    assert!(!data_matches_masks(
+      &data,
+      &[data_mask(90, vec![0xff; 8], vec![0; 8], true)]
+    ));
+  }
+
+  #[test]
+  fn test_validate_rejects_out_of_range_offset() {
+    let masks = [data_mask(
+      MAX_DATA_MASK_END as u32 - 16,
+      vec![0xff; 32],
+      vec![0; 32],
+      false,
+    )];
+    assert!(validate_data_masks(&masks).is_err());
+
+    let masks = [data_mask(u32::MAX, vec![0xff], vec![0], false)];
+    assert!(validate_data_masks(&masks).is_err());
+  }
+
+  #[test]
+  fn test_validate_rejects_malformed_masks() {
+    assert!(validate_data_masks(&[data_mask(0, vec![], vec![], false)]).is_err());
+    assert!(validate_data_masks(&[data_mask(0, vec![0xff; 2], vec![0], false)]).is_err());
+    assert!(validate_data_masks(&[data_mask(0, vec![0xff; 32], vec![0; 32], false)]).is_ok());
+  }
+}
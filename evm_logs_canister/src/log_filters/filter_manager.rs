@@ -152,6 +152,8 @@ mod tests {
           })
           .collect()
       }),
+      predicate: None,
+      data_masks: None,
     }
   }
 
@@ -0,0 +1,492 @@
+use std::cmp::Ordering;
+
+use evm_logs_types::{AbiParamKind, CompareOp, EventParam, EventPredicate, Predicate, PredicateValue};
+use evm_rpc_types::LogEntry;
+
+/// Size of one ABI word (topic or head slot of `data`)
+const WORD_SIZE: usize = 32;
+/// Topics beyond the signature topic (topic0) that can hold indexed parameters
+const MAX_INDEXED_PARAMS: usize = 3;
+/// Upper bound for the number of nodes in a predicate tree, keeps evaluation cost per event bounded
+const MAX_PREDICATE_NODES: usize = 64;
+/// Upper bound for predicate nesting
+const MAX_PREDICATE_DEPTH: usize = 8;
+
+type Word = [u8; WORD_SIZE];
+
+/// Evaluates the predicate against the log. Any field that can't be decoded (missing topic,
+/// `data` shorter than expected) makes the comparison false.
+pub fn event_matches_predicate(log_entry: &LogEntry, predicate: &EventPredicate) -> bool {
+  evaluate(&predicate.condition, &predicate.params, log_entry)
+}
+
+fn evaluate(condition: &Predicate, params: &[EventParam], log_entry: &LogEntry) -> bool {
+  match condition {
+    Predicate::Compare { field, op, value } => {
+      let Some(param) = params.iter().find(|param| param.name == *field) else {
+        return false;
+      };
+      decode_word(param, params, log_entry).is_some_and(|word| compare(&word, &param.kind, op, value))
+    }
+    Predicate::And(conditions) => conditions.iter().all(|c| evaluate(c, params, log_entry)),
+    Predicate::Or(conditions) => conditions.iter().any(|c| evaluate(c, params, log_entry)),
+    Predicate::Not(condition) => !evaluate(condition, params, log_entry),
+  }
+}
+
+/// Indexed parameters are taken from `topics[1..]`, the rest from consecutive head words of `data`.
+fn decode_word(param: &EventParam, params: &[EventParam], log_entry: &LogEntry) -> Option<Word> {
+  let position = params
+    .iter()
+    .take_while(|p| p.name != param.name)
+    .filter(|p| p.indexed == param.indexed)
+    .count();
+
+  if param.indexed {
+    log_entry.topics.get(position + 1)?.as_ref().try_into().ok()
+  } else {
+    let data: &[u8] = log_entry.data.as_ref();
+    let start = position * WORD_SIZE;
+    data.get(start..start + WORD_SIZE)?.try_into().ok()
+  }
+}
+
+fn compare(word: &Word, kind: &AbiParamKind, op: &CompareOp, value: &PredicateValue) -> bool {
+  let ordering = match (kind, value) {
+    (AbiParamKind::Uint, PredicateValue::Nat(_))
+    | (AbiParamKind::Int, PredicateValue::Nat(_) | PredicateValue::Int(_)) => {
+      let Some(expected) = numeric_word(kind, value) else {
+        return false;
+      };
+      if *kind == AbiParamKind::Int {
+        cmp_signed(word, &expected)
+      } else {
+        word.cmp(&expected)
+      }
+    }
+    (AbiParamKind::Address, PredicateValue::Address(address)) => word[WORD_SIZE - 20..].cmp(address.as_ref()),
+    (AbiParamKind::Bool, PredicateValue::Bool(expected)) => (*word != [0u8; WORD_SIZE]).cmp(expected),
+    (AbiParamKind::FixedBytes, PredicateValue::Bytes(bytes)) if bytes.len() <= WORD_SIZE => {
+      word[..bytes.len()].cmp(bytes.as_slice())
+    }
+    _ => return false,
+  };
+
+  match op {
+    CompareOp::Eq => ordering == Ordering::Equal,
+    CompareOp::Ne => ordering != Ordering::Equal,
+    CompareOp::Lt => ordering == Ordering::Less,
+    CompareOp::Le => ordering != Ordering::Greater,
+    CompareOp::Gt => ordering == Ordering::Greater,
+    CompareOp::Ge => ordering != Ordering::Less,
+  }
+}
+
+/// Two's complement comparison: flipping the sign bit turns it into an unsigned one.
+fn cmp_signed(left: &Word, right: &Word) -> Ordering {
+  let (mut left, mut right) = (*left, *right);
+  left[0] ^= 0x80;
+  right[0] ^= 0x80;
+  left.cmp(&right)
+}
+
+/// Big-endian 256-bit representation of a numeric value, `None` if it doesn't fit.
+fn value_to_word(value: &PredicateValue) -> Option<Word> {
+  let (bytes, fill) = match value {
+    PredicateValue::Nat(nat) => (nat.0.to_bytes_be(), 0x00),
+    PredicateValue::Int(int) => {
+      let bytes = int.0.to_signed_bytes_be();
+      let fill = if bytes.first().is_some_and(|byte| byte & 0x80 != 0) {
+        0xff
+      } else {
+        0x00
+      };
+      (bytes, fill)
+    }
+    _ => return None,
+  };
+
+  if bytes.len() > WORD_SIZE {
+    return None;
+  }
+
+  let mut word = [fill; WORD_SIZE];
+  word[WORD_SIZE - bytes.len()..].copy_from_slice(&bytes);
+  Some(word)
+}
+
+/// Word of a numeric value compared to a field of the kind, `None` if it's out of the field's range.
+/// A `Nat` compared to an `int256` field must not exceed `i256::MAX`, it would be read as negative otherwise.
+fn numeric_word(kind: &AbiParamKind, value: &PredicateValue) -> Option<Word> {
+  let word = value_to_word(value)?;
+  match (kind, value) {
+    (AbiParamKind::Int, PredicateValue::Nat(_)) if word[0] & 0x80 != 0 => None,
+    _ => Some(word),
+  }
+}
+
+/// Checks the predicate at registration time, so that evaluation never has to deal with
+/// unknown fields or mismatched types.
+pub fn validate_predicate(predicate: &EventPredicate) -> Result<(), String> {
+  let params = &predicate.params;
+
+  for (i, param) in params.iter().enumerate() {
+    if param.name.is_empty() {
+      return Err(format!("Parameter #{} has an empty name", i));
+    }
+    if params[..i].iter().any(|p| p.name == param.name) {
+      return Err(format!("Duplicate parameter name: {}", param.name));
+    }
+  }
+
+  if params.iter().filter(|p| p.indexed).count() > MAX_INDEXED_PARAMS {
+    return Err(format!("At most {} parameters can be indexed", MAX_INDEXED_PARAMS));
+  }
+
+  let mut nodes = 0;
+  validate_condition(&predicate.condition, params, 1, &mut nodes)
+}
+
+fn validate_condition(
+  condition: &Predicate,
+  params: &[EventParam],
+  depth: usize,
+  nodes: &mut usize,
+) -> Result<(), String> {
+  *nodes += 1;
+  if *nodes > MAX_PREDICATE_NODES {
+    return Err(format!("Predicate has more than {} nodes", MAX_PREDICATE_NODES));
+  }
+  if depth > MAX_PREDICATE_DEPTH {
+    return Err(format!(
+      "Predicate is nested deeper than {} levels",
+      MAX_PREDICATE_DEPTH
+    ));
+  }
+
+  match condition {
+    Predicate::Compare { field, op, value } => {
+      let param = params
+        .iter()
+        .find(|param| param.name == *field)
+        .ok_or_else(|| format!("Unknown field: {}", field))?;
+      validate_comparison(param, op, value)
+    }
+    Predicate::And(conditions) | Predicate::Or(conditions) => {
+      if conditions.is_empty() {
+        return Err("And/Or requires at least one operand".to_string());
+      }
+      conditions
+        .iter()
+        .try_for_each(|c| validate_condition(c, params, depth + 1, nodes))
+    }
+    Predicate::Not(condition) => validate_condition(condition, params, depth + 1, nodes),
+  }
+}
+
+fn validate_comparison(param: &EventParam, op: &CompareOp, value: &PredicateValue) -> Result<(), String> {
+  let is_equality = matches!(op, CompareOp::Eq | CompareOp::Ne);
+
+  match (&param.kind, value) {
+    (AbiParamKind::Uint, PredicateValue::Nat(_))
+    | (AbiParamKind::Int, PredicateValue::Nat(_) | PredicateValue::Int(_)) => {
+      if numeric_word(&param.kind, value).is_none() {
+        return Err(format!(
+          "Value for {} is out of the {:?} 256-bit range",
+          param.name, param.kind
+        ));
+      }
+      Ok(())
+    }
+    (AbiParamKind::Address, PredicateValue::Address(_)) | (AbiParamKind::Bool, PredicateValue::Bool(_))
+      if is_equality =>
+    {
+      Ok(())
+    }
+    (AbiParamKind::FixedBytes, PredicateValue::Bytes(bytes)) if is_equality => {
+      if bytes.is_empty() || bytes.len() > WORD_SIZE {
+        return Err(format!("Value for {} must be 1..=32 bytes long", param.name));
+      }
+      Ok(())
+    }
+    (AbiParamKind::Dynamic, _) => Err(format!("Dynamic field {} can't be compared", param.name)),
+    _ => Err(format!(
+      "Unsupported comparison {:?} {:?} for field {}",
+      op, value, param.name
+    )),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::str::FromStr;
+
+  use candid::{Int, Nat};
+  use evm_rpc_types::{Hex, Hex20, Hex32};
+
+  use super::*;
+
+  const TRANSFER_TOPIC: &str = "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef";
+  const FROM_ADDR: &str = "0xd42AcA6E135D1dae6317e776F7EB96Eb91b8eb91";
+  const TO_ADDR: &str = "0xDA2efffa45cf5D960209aA0921Cf42a4a2a085cf";
+
+  fn address_topic(address: &str) -> Hex32 {
+    let mut word = [0u8; WORD_SIZE];
+    word[12..].copy_from_slice(Hex20::from_str(address).unwrap().as_ref());
+    Hex32::from(word)
+  }
+
+  fn uint_word(value: u128) -> Vec<u8> {
+    let mut word = vec![0u8; 16];
+    word.extend_from_slice(&value.to_be_bytes());
+    word
+  }
+
+  fn int_word(value: i128) -> Vec<u8> {
+    let mut word = vec![if value < 0 { 0xff } else { 0x00 }; 16];
+    word.extend_from_slice(&value.to_be_bytes());
+    word
+  }
+
+  fn log_entry(topics: Vec<Hex32>, data: Vec<u8>) -> LogEntry {
+    LogEntry {
+      address: Hex20::from_str(FROM_ADDR).unwrap(),
+      topics,
+      data: Hex::from(data),
+      block_number: None,
+      transaction_hash: None,
+      transaction_index: None,
+      block_hash: None,
+      log_index: None,
+      removed: false,
+    }
+  }
+
+  // Transfer(address indexed from, address indexed to, uint256 value)
+  fn transfer_params() -> Vec<EventParam> {
+    vec![
+      EventParam {
+        name: "from".to_string(),
+        kind: AbiParamKind::Address,
+        indexed: true,
+      },
+      EventParam {
+        name: "to".to_string(),
+        kind: AbiParamKind::Address,
+        indexed: true,
+      },
+      EventParam {
+        name: "value".to_string(),
+        kind: AbiParamKind::Uint,
+        indexed: false,
+      },
+    ]
+  }
+
+  fn transfer_log(value: u128) -> LogEntry {
+    log_entry(
+      vec![
+        Hex32::from_str(TRANSFER_TOPIC).unwrap(),
+        address_topic(FROM_ADDR),
+        address_topic(TO_ADDR),
+      ],
+      uint_word(value),
+    )
+  }
+
+  // Swap(address indexed sender, address indexed recipient, int256 amount0, int256 amount1)
+  fn swap_predicate(condition: Predicate) -> EventPredicate {
+    EventPredicate {
+      params: vec![
+        EventParam {
+          name: "sender".to_string(),
+          kind: AbiParamKind::Address,
+          indexed: true,
+        },
+        EventParam {
+          name: "recipient".to_string(),
+          kind: AbiParamKind::Address,
+          indexed: true,
+        },
+        EventParam {
+          name: "amount0".to_string(),
+          kind: AbiParamKind::Int,
+          indexed: false,
+        },
+        EventParam {
+          name: "amount1".to_string(),
+          kind: AbiParamKind::Int,
+          indexed: false,
+        },
+      ],
+      condition,
+    }
+  }
+
+  fn swap_log(amount0: i128, amount1: i128) -> LogEntry {
+    let mut data = int_word(amount0);
+    data.extend(int_word(amount1));
+    log_entry(
+      vec![
+        Hex32::from_str(TRANSFER_TOPIC).unwrap(),
+        address_topic(FROM_ADDR),
+        address_topic(TO_ADDR),
+      ],
+      data,
+    )
+  }
+
+  fn compare_value(field: &str, op: CompareOp, value: PredicateValue) -> Predicate {
+    Predicate::Compare {
+      field: field.to_string(),
+      op,
+      value,
+    }
+  }
+
+  #[test]
+  fn test_uint_greater_than() {
+    let predicate = EventPredicate {
+      params: transfer_params(),
+      condition: compare_value(
+        "value",
+        CompareOp::Gt,
+        PredicateValue::Nat(Nat::from(1_000_000_000_000u64)),
+      ),
+    };
+
+    assert!(event_matches_predicate(&transfer_log(1_000_000_000_001), &predicate));
+    assert!(!event_matches_predicate(&transfer_log(1_000_000_000_000), &predicate));
+    assert!(!event_matches_predicate(&transfer_log(5), &predicate));
+  }
+
+  #[test]
+  fn test_signed_less_than_zero() {
+    let predicate = swap_predicate(compare_value(
+      "amount0",
+      CompareOp::Lt,
+      PredicateValue::Int(Int::from(0)),
+    ));
+
+    assert!(event_matches_predicate(&swap_log(-1, 10), &predicate));
+    assert!(event_matches_predicate(&swap_log(i128::MIN, 10), &predicate));
+    assert!(!event_matches_predicate(&swap_log(0, -10), &predicate));
+    assert!(!event_matches_predicate(&swap_log(7, -10), &predicate));
+  }
+
+  #[test]
+  fn test_indexed_address_and_combinators() {
+    let to_address = Hex20::from_str(TO_ADDR).unwrap();
+    let predicate = EventPredicate {
+      params: transfer_params(),
+      condition: Predicate::And(vec![
+        compare_value("to", CompareOp::Eq, PredicateValue::Address(to_address)),
+        Predicate::Not(Box::new(compare_value(
+          "value",
+          CompareOp::Eq,
+          PredicateValue::Nat(Nat::from(0u32)),
+        ))),
+      ]),
+    };
+
+    assert!(event_matches_predicate(&transfer_log(3), &predicate));
+    assert!(!event_matches_predicate(&transfer_log(0), &predicate));
+
+    let from_address = Hex20::from_str(FROM_ADDR).unwrap();
+    let predicate = EventPredicate {
+      params: transfer_params(),
+      condition: Predicate::Or(vec![
+        compare_value("to", CompareOp::Eq, PredicateValue::Address(from_address)),
+        compare_value("value", CompareOp::Ge, PredicateValue::Nat(Nat::from(3u32))),
+      ]),
+    };
+
+    assert!(event_matches_predicate(&transfer_log(3), &predicate));
+    assert!(!event_matches_predicate(&transfer_log(2), &predicate));
+  }
+
+  #[test]
+  fn test_missing_data_does_not_match() {
+    let predicate = EventPredicate {
+      params: transfer_params(),
+      condition: compare_value("value", CompareOp::Ge, PredicateValue::Nat(Nat::from(0u32))),
+    };
+    let truncated = log_entry(vec![Hex32::from_str(TRANSFER_TOPIC).unwrap()], vec![0u8; 16]);
+
+    assert!(!event_matches_predicate(&truncated, &predicate));
+  }
+
+  #[test]
+  fn test_validate_rejects_unknown_field() {
+    let predicate = EventPredicate {
+      params: transfer_params(),
+      condition: compare_value("amount", CompareOp::Gt, PredicateValue::Nat(Nat::from(1u32))),
+    };
+
+    assert!(validate_predicate(&predicate).is_err());
+  }
+
+  #[test]
+  fn test_validate_rejects_type_mismatch() {
+    let predicate = EventPredicate {
+      params: transfer_params(),
+      condition: compare_value("to", CompareOp::Gt, PredicateValue::Nat(Nat::from(1u32))),
+    };
+    assert!(validate_predicate(&predicate).is_err());
+
+    let predicate = EventPredicate {
+      params: transfer_params(),
+      condition: compare_value("value", CompareOp::Lt, PredicateValue::Int(Int::from(-1))),
+    };
+    assert!(validate_predicate(&predicate).is_err());
+  }
+
+  #[test]
+  fn test_validate_rejects_nat_beyond_int256() {
+    let i256_max = (0..255).fold(Nat::from(1u32), |nat, _| nat * 2u32) - 1u32;
+
+    let predicate = swap_predicate(compare_value(
+      "amount0",
+      CompareOp::Gt,
+      PredicateValue::Nat(i256_max.clone()),
+    ));
+    assert!(validate_predicate(&predicate).is_ok());
+    assert!(!event_matches_predicate(&swap_log(-1, 0), &predicate));
+
+    let predicate = swap_predicate(compare_value(
+      "amount0",
+      CompareOp::Gt,
+      PredicateValue::Nat(i256_max + 1u32),
+    ));
+    assert!(validate_predicate(&predicate).is_err());
+    assert!(!event_matches_predicate(&swap_log(-1, 0), &predicate));
+  }
+
+  #[test]
+  fn test_validate_rejects_too_many_indexed() {
+    let mut params = transfer_params();
+    for name in ["a", "b"] {
+      params.push(EventParam {
+        name: name.to_string(),
+        kind: AbiParamKind::Uint,
+        indexed: true,
+      });
+    }
+    let predicate = EventPredicate {
+      params,
+      condition: compare_value("value", CompareOp::Gt, PredicateValue::Nat(Nat::from(1u32))),
+    };
+
+    assert!(validate_predicate(&predicate).is_err());
+  }
+
+  #[test]
+  fn test_validate_accepts_valid_predicate() {
+    let predicate = swap_predicate(Predicate::Or(vec![
+      compare_value("amount0", CompareOp::Lt, PredicateValue::Int(Int::from(0))),
+      compare_value("amount1", CompareOp::Gt, PredicateValue::Nat(Nat::from(10u32))),
+    ]));
+
+    assert!(validate_predicate(&predicate).is_ok());
+  }
+}
@@ -1,4 +1,12 @@
+pub mod coalescing;
+pub mod deposits;
 pub mod events_publisher;
+pub mod expiry;
+pub mod grouping;
+pub mod pausing;
 pub mod queries;
+pub mod quotes;
+pub mod stats;
 pub mod subscription;
 pub mod utils;
+pub mod withdrawals;
@@ -0,0 +1,246 @@
+use std::collections::VecDeque;
+
+use candid::{CandidType, Nat};
+use evm_logs_types::{CoalesceConfig, CoalesceKey, CoalesceWindow, Event};
+use serde::{Deserialize, Serialize};
+
+use crate::{STATE, constants::MAX_COALESCED_KEYS, internals::misc::timestamp_nanos};
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+/// Topics a log can have, including the signature topic
+const MAX_TOPIC_POSITIONS: u8 = 4;
+
+/// Matched events of one coalescing subscription, waiting for the current window to close.
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct CoalesceBuffer {
+  pub window_started_at: u64, // UTC Nanoseconds
+  pub window_start_block: Option<Nat>,
+  /// Latest event per coalescing key, in order of first appearance of the key
+  pub pending: VecDeque<(Vec<u8>, Event)>,
+}
+
+impl CoalesceBuffer {
+  fn new(event: &Event) -> Self {
+    Self {
+      window_started_at: timestamp_nanos(),
+      window_start_block: event.log_entry.block_number.clone().map(Nat::from),
+      pending: VecDeque::new(),
+    }
+  }
+
+  /// Returns `true` if an older event with the same key was replaced,
+  /// or the event of the oldest key was dropped to keep `capacity` keys at most
+  fn push(&mut self, key: Vec<u8>, event: Event, capacity: usize) -> bool {
+    match self.pending.iter_mut().find(|(pending_key, _)| *pending_key == key) {
+      Some((_, pending_event)) => {
+        *pending_event = event;
+        true
+      }
+      None => {
+        self.pending.push_back((key, event));
+        self.pending.len() > capacity && self.pending.pop_front().is_some()
+      }
+    }
+  }
+
+  /// Window is closed once enough time or blocks passed since the first buffered event.
+  /// Block windows without known block numbers are closed right away.
+  fn is_due(&self, window: &CoalesceWindow, now: u64, current_block: Option<&Nat>) -> bool {
+    match window {
+      CoalesceWindow::Seconds(seconds) => {
+        now.saturating_sub(self.window_started_at) >= seconds.saturating_mul(NANOS_PER_SEC)
+      }
+      CoalesceWindow::Blocks(blocks) => match (&self.window_start_block, current_block) {
+        (Some(start), Some(current)) => *current >= start.clone() + Nat::from(*blocks),
+        _ => true,
+      },
+    }
+  }
+}
+
+fn coalesce_key(event: &Event, key: &CoalesceKey) -> Vec<u8> {
+  match key {
+    CoalesceKey::Address => event.log_entry.address.as_ref().to_vec(),
+    CoalesceKey::Topic(position) => event
+      .log_entry
+      .topics
+      .get(*position as usize)
+      .map(|topic| topic.as_ref().to_vec())
+      .unwrap_or_default(),
+  }
+}
+
+/// Puts the event into the subscription's buffer, replacing an older event with the same key.
+/// Returns `true` if an older event was replaced or dropped.
+pub fn buffer_event(sub_id: &Nat, config: &CoalesceConfig, event: &Event) -> bool {
+  let key = coalesce_key(event, &config.key);
+
+  STATE.with(|state| {
+    state
+      .borrow_mut()
+      .coalesce_buffers
+      .entry(sub_id.clone())
+      .or_insert_with(|| CoalesceBuffer::new(event))
+      .push(key, event.clone(), MAX_COALESCED_KEYS)
+  })
+}
+
+/// Removes and returns the buffered events of the subscription if its window is closed.
+pub fn take_due_events(sub_id: &Nat, config: &CoalesceConfig, current_block: Option<&Nat>) -> Vec<Event> {
+  let now = timestamp_nanos();
+
+  STATE.with(|state| {
+    let mut state = state.borrow_mut();
+    let is_due = state
+      .coalesce_buffers
+      .get(sub_id)
+      .is_some_and(|buffer| buffer.is_due(&config.window, now, current_block));
+
+    if !is_due {
+      return Vec::new();
+    }
+
+    state
+      .coalesce_buffers
+      .remove(sub_id)
+      .map(|buffer| buffer.pending.into_iter().map(|(_, event)| event).collect())
+      .unwrap_or_default()
+  })
+}
+
+pub fn drop_buffer(sub_id: &Nat) {
+  STATE.with(|state| {
+    state.borrow_mut().coalesce_buffers.remove(sub_id);
+  });
+}
+
+pub fn validate_coalesce_config(config: &CoalesceConfig) -> Result<(), String> {
+  match config.window {
+    CoalesceWindow::Blocks(0) | CoalesceWindow::Seconds(0) => {
+      return Err("Coalescing window must be greater than zero".to_string());
+    }
+    _ => {}
+  }
+
+  if matches!(config.key, CoalesceKey::Topic(position) if position >= MAX_TOPIC_POSITIONS) {
+    return Err(format!(
+      "Coalescing topic position must be below {}",
+      MAX_TOPIC_POSITIONS
+    ));
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use std::str::FromStr;
+
+  use evm_rpc_types::{Hex, Hex20, Hex32, LogEntry};
+
+  use super::*;
+
+  const ADDR1_HEX20: &str = "0xd42AcA6E135D1dae6317e776F7EB96Eb91b8eb91";
+  const ADDR2_HEX20: &str = "0xDA2efffa45cf5D960209aA0921Cf42a4a2a085cf";
+
+  fn create_event(address: &str, block_number: u64, data: u8) -> Event {
+    Event {
+      id: Nat::from(data),
+      timestamp: 0,
+      chain_id: 1,
+      log_entry: LogEntry {
+        address: Hex20::from_str(address).unwrap(),
+        topics: vec![Hex32::from([data; 32])],
+        data: Hex::from(vec![data]),
+        block_number: Some(block_number.into()),
+        transaction_hash: None,
+        transaction_index: None,
+        block_hash: None,
+        log_index: None,
+        removed: false,
+      },
+    }
+  }
+
+  fn buffer_with(events: &[Event], key: &CoalesceKey) -> CoalesceBuffer {
+    let mut buffer = CoalesceBuffer {
+      window_started_at: 0,
+      window_start_block: events[0].log_entry.block_number.clone().map(Nat::from),
+      pending: VecDeque::new(),
+    };
+    for event in events {
+      buffer.push(coalesce_key(event, key), event.clone(), MAX_COALESCED_KEYS);
+    }
+    buffer
+  }
+
+  #[test]
+  fn test_keeps_latest_event_per_address() {
+    let events = [
+      create_event(ADDR1_HEX20, 10, 1),
+      create_event(ADDR2_HEX20, 10, 2),
+      create_event(ADDR1_HEX20, 11, 3),
+    ];
+    let buffer = buffer_with(&events, &CoalesceKey::Address);
+
+    let data: Vec<u8> = buffer
+      .pending
+      .iter()
+      .map(|(_, event)| event.log_entry.data.as_ref()[0])
+      .collect();
+    assert_eq!(data, vec![3, 2]);
+  }
+
+  #[test]
+  fn test_topic_key_separates_same_address() {
+    let events = [create_event(ADDR1_HEX20, 10, 1), create_event(ADDR1_HEX20, 10, 2)];
+    let buffer = buffer_with(&events, &CoalesceKey::Topic(0));
+
+    assert_eq!(buffer.pending.len(), 2);
+  }
+
+  #[test]
+  fn test_oldest_key_is_dropped_when_full() {
+    let events = [
+      create_event(ADDR1_HEX20, 10, 1),
+      create_event(ADDR1_HEX20, 10, 2),
+      create_event(ADDR1_HEX20, 10, 3),
+    ];
+    let mut buffer = buffer_with(&events[..2], &CoalesceKey::Topic(0));
+
+    assert!(buffer.push(coalesce_key(&events[2], &CoalesceKey::Topic(0)), events[2].clone(), 2));
+    let data: Vec<u8> = buffer
+      .pending
+      .iter()
+      .map(|(_, event)| event.log_entry.data.as_ref()[0])
+      .collect();
+    assert_eq!(data, vec![2, 3]);
+  }
+
+  #[test]
+  fn test_block_window_is_due() {
+    let buffer = buffer_with(&[create_event(ADDR1_HEX20, 100, 1)], &CoalesceKey::Address);
+    let window = CoalesceWindow::Blocks(5);
+
+    assert!(!buffer.is_due(&window, 0, Some(&Nat::from(104u32))));
+    assert!(buffer.is_due(&window, 0, Some(&Nat::from(105u32))));
+  }
+
+  #[test]
+  fn test_seconds_window_is_due() {
+    let buffer = buffer_with(&[create_event(ADDR1_HEX20, 100, 1)], &CoalesceKey::Address);
+    let window = CoalesceWindow::Seconds(30);
+
+    assert!(!buffer.is_due(&window, 29 * NANOS_PER_SEC, None));
+    assert!(buffer.is_due(&window, 30 * NANOS_PER_SEC, None));
+  }
+
+  #[test]
+  fn test_validate_coalesce_config() {
+    let config = |window, key| CoalesceConfig { window, key };
+
+    assert!(validate_coalesce_config(&config(CoalesceWindow::Blocks(0), CoalesceKey::Address)).is_err());
+    assert!(validate_coalesce_config(&config(CoalesceWindow::Seconds(10), CoalesceKey::Topic(4))).is_err());
+    assert!(validate_coalesce_config(&config(CoalesceWindow::Seconds(10), CoalesceKey::Topic(1))).is_ok());
+  }
+}
@@ -0,0 +1,179 @@
+use candid::{Nat, Principal};
+use evm_logs_types::{AdminError, AdminResult, BalanceChangeKind, DepositError, DepositResult, PaymentLedger};
+use ic_cdk::api::call::call;
+
+use super::subscription::restore_funded_subscriptions;
+use crate::{
+  STATE, get_state_value, log_with_metrics,
+  types::{
+    balance_ledger::{ChargeReference, record_balance_change},
+    balances::Balances,
+    icrc::{Account, TransferFromArgs, TransferFromResult},
+  },
+};
+
+pub fn get_payment_ledgers() -> Vec<PaymentLedger> {
+  get_state_value!(payment_ledgers)
+}
+
+/// Adds the ledger or replaces its rate.
+pub fn set_payment_ledger(payment_ledger: PaymentLedger) -> AdminResult {
+  if payment_ledger.cycles_numerator == 0u32 || payment_ledger.cycles_denominator == 0u32 {
+    return AdminResult::Err(AdminError::ImproperConfig(
+      "Rate numerator and denominator must be positive".to_string(),
+    ));
+  }
+
+  STATE.with(|state| {
+    let ledgers = &mut state.borrow_mut().payment_ledgers;
+    ledgers.retain(|ledger| ledger.ledger != payment_ledger.ledger);
+    ledgers.push(payment_ledger);
+  });
+  AdminResult::Ok
+}
+
+pub fn remove_payment_ledger(ledger: Principal) -> AdminResult {
+  STATE.with(|state| {
+    let ledgers = &mut state.borrow_mut().payment_ledgers;
+    let count = ledgers.len();
+    ledgers.retain(|payment_ledger| payment_ledger.ledger != ledger);
+
+    if ledgers.len() == count {
+      AdminResult::Err(AdminError::ImproperConfig(format!(
+        "Ledger {} is not configured",
+        ledger
+      )))
+    } else {
+      AdminResult::Ok
+    }
+  })
+}
+
+/// Cycles `amount` tokens of the ledger are worth, rounded down.
+fn cycles_for(ledger: &Principal, amount: &Nat) -> Result<Nat, DepositError> {
+  let payment_ledger = STATE.with(|state| {
+    state
+      .borrow()
+      .payment_ledgers
+      .iter()
+      .find(|payment_ledger| payment_ledger.ledger == *ledger)
+      .cloned()
+  });
+  let payment_ledger = payment_ledger.ok_or(DepositError::UnsupportedLedger)?;
+
+  let cycles = amount.clone() * payment_ledger.cycles_numerator / payment_ledger.cycles_denominator;
+  if cycles == 0u32 {
+    return Err(DepositError::AmountTooSmall);
+  }
+
+  Ok(cycles)
+}
+
+/// Moves `amount` tokens approved by `payer` to this canister with `icrc2_transfer_from`
+/// and credits their worth in cycles to the balance of `canister_to_top_up`.
+pub async fn deposit(payer: Principal, ledger: Principal, amount: Nat, canister_to_top_up: Principal) -> DepositResult {
+  let cycles = match cycles_for(&ledger, &amount) {
+    Ok(cycles) => cycles,
+    Err(err) => return DepositResult::Err(err),
+  };
+
+  let args = TransferFromArgs {
+    spender_subaccount: None,
+    from: Account::from(payer),
+    to: Account::from(ic_cdk::id()),
+    amount: amount.clone(),
+    fee: None,
+    memo: None,
+    created_at_time: None,
+  };
+  let result: Result<(TransferFromResult,), _> = call(ledger, "icrc2_transfer_from", (args,)).await;
+
+  let block_index = match result {
+    Ok((Ok(block_index),)) => block_index,
+    Ok((Err(err),)) => return DepositResult::Err(DepositError::TransferFailed(format!("{:?}", err))),
+    Err((code, message)) => {
+      return DepositResult::Err(DepositError::TransferFailed(format!("{:?}: {}", code, message)));
+    }
+  };
+
+  Balances::top_up(canister_to_top_up, cycles.clone()).unwrap();
+  record_balance_change(
+    canister_to_top_up,
+    BalanceChangeKind::Deposit { ledger, block_index },
+    cycles.clone(),
+    ChargeReference::default(),
+  );
+  restore_funded_subscriptions(canister_to_top_up);
+
+  log_with_metrics!(
+    "Deposited {} tokens of {} from {} as {} cycles for {}",
+    amount,
+    ledger.to_text(),
+    payer.to_text(),
+    cycles,
+    canister_to_top_up.to_text()
+  );
+
+  DepositResult::Ok(cycles)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn create_payment_ledger(numerator: u64, denominator: u64) -> PaymentLedger {
+    PaymentLedger {
+      ledger: Principal::from_slice(&[1]),
+      token_symbol: "ckETH".to_string(),
+      cycles_numerator: Nat::from(numerator),
+      cycles_denominator: Nat::from(denominator),
+    }
+  }
+
+  #[test]
+  fn test_cycles_for_configured_rate() {
+    set_payment_ledger(create_payment_ledger(3, 2));
+    let ledger = Principal::from_slice(&[1]);
+
+    assert_eq!(cycles_for(&ledger, &Nat::from(1_000u32)), Ok(Nat::from(1_500u32)));
+    assert_eq!(cycles_for(&ledger, &Nat::from(1u32)), Ok(Nat::from(1u32)));
+    assert_eq!(cycles_for(&ledger, &Nat::from(0u32)), Err(DepositError::AmountTooSmall));
+  }
+
+  #[test]
+  fn test_cycles_for_unsupported_ledger() {
+    assert_eq!(
+      cycles_for(&Principal::from_slice(&[2]), &Nat::from(1_000u32)),
+      Err(DepositError::UnsupportedLedger)
+    );
+  }
+
+  #[test]
+  fn test_set_payment_ledger_replaces_rate() {
+    assert_eq!(set_payment_ledger(create_payment_ledger(1, 1)), AdminResult::Ok);
+    assert_eq!(set_payment_ledger(create_payment_ledger(5, 1)), AdminResult::Ok);
+
+    assert_eq!(get_payment_ledgers(), vec![create_payment_ledger(5, 1)]);
+  }
+
+  #[test]
+  fn test_set_payment_ledger_rejects_zero_rate() {
+    assert!(matches!(
+      set_payment_ledger(create_payment_ledger(1, 0)),
+      AdminResult::Err(AdminError::ImproperConfig(_))
+    ));
+    assert!(get_payment_ledgers().is_empty());
+  }
+
+  #[test]
+  fn test_remove_payment_ledger() {
+    set_payment_ledger(create_payment_ledger(1, 1));
+
+    assert_eq!(remove_payment_ledger(Principal::from_slice(&[1])), AdminResult::Ok);
+    assert!(get_payment_ledgers().is_empty());
+    assert!(matches!(
+      remove_payment_ledger(Principal::from_slice(&[1])),
+      AdminResult::Err(AdminError::ImproperConfig(_))
+    ));
+  }
+}
@@ -1,138 +1,411 @@
-use candid::Nat;
-use evm_logs_types::{Event, EventNotification, SendNotificationError, SendNotificationResult};
-use ic_cdk::{self, api::call::call};
+use candid::{Decode, Encode, Nat};
+use evm_logs_types::{
+  BalanceChangeKind, DeliveryMode, Event, EventNotification, PauseMode, SendNotificationError, SendNotificationResult,
+  SubscriptionInfo, SubscriptionNotice, SubscriptionNoticeKind, SubscriptionStatus, TransactionLogs,
+};
+use ic_cdk::{
+  self,
+  api::call::{call, call_raw},
+};
 
-use super::utils::event_matches_filter;
+use super::{
+  coalescing::{buffer_event, take_due_events},
+  expiry::is_within_expiry,
+  grouping::{TransactionGroups, transaction_logs},
+  pausing::buffer_paused_event,
+  stats::{record_charged, record_delivered, record_failed, record_matched, record_skipped},
+  subscription::{remove_subscription, start_grace_period, suspend_subscription},
+  utils::event_matches_filter,
+};
 use crate::{
-  FILTERS_MANAGER, NEXT_NOTIFICATION_ID, constants::*, get_state_value, internals::misc::timestamp_nanos,
-  log_with_metrics, types::balances::Balances,
+  NEXT_NOTIFICATION_ID,
+  constants::*,
+  get_state_value,
+  internals::misc::timestamp_nanos,
+  log_with_metrics,
+  types::{
+    balance_ledger::{ChargeReference, record_balance_change},
+    balances::Balances,
+    pricing::{delivery_price, get_pricing},
+  },
 };
 
 /// Approximate size of a response payload(just Ok response)
 const OK_RESP_SIZE: u64 = 32;
 
-fn estimate_cycles_for_event_notification(event_size: usize) -> u64 {
-  // Estimated request size (event notification structure)
-  let request_size = event_size as u64; // Size in bytes of the event notification payload
+/// Cycles spent on a `send_notification` call with `payload_size` bytes of Candid-encoded arguments
+fn notification_cost(payload_size: usize) -> u64 {
+  let request_size = payload_size as u64;
   let response_size = OK_RESP_SIZE;
 
   // Compute cycles based on transmission costs
   let cycles_for_request = request_size * CYCLES_PER_BYTE_SEND;
   let cycles_for_response = response_size * CYCLES_PER_BYTE_RECEIVE;
 
-  // Total estimated cycles including the base call cost
+  // Total cycles including the base call cost
   BASE_CALL_CYCLES + cycles_for_request + cycles_for_response
 }
 
+/// Cycles a subscriber needs for one more notification on the chain, only a pre-flight check:
+/// delivered notifications are charged by their encoded size, see `notification_cost`.
+pub fn notification_cost_estimate(chain_id: u32) -> u64 {
+  delivery_price(
+    &get_pricing(),
+    chain_id,
+    notification_cost(EVM_EVENT_SIZE_BYTES as usize),
+    1,
+  )
+}
+
 pub async fn publish_events(events: Vec<Event>) {
+  let mut transaction_groups = TransactionGroups::default();
+
   for event in events {
     // all errors are being handled there individually for each event
-    distribute_event(event).await;
+    distribute_event(event, &mut transaction_groups).await;
   }
+
+  deliver_transaction_groups(transaction_groups).await;
 }
 
-/// Distribute event to corresponding subscribers and handle sending errors
-async fn distribute_event(event: Event) {
-  // Get all subscriptions for the event's chain_id
-  let subscriptions = crate::STATE.with(|state| {
+/// Deliver events to a single subscription only, e.g. the ones it missed while excluded from fetching
+pub async fn publish_events_to(sub: &SubscriptionInfo, events: Vec<Event>) {
+  let matched: Vec<Event> = events
+    .into_iter()
+    .filter(|event| event_matches_filter(event, &sub.filter))
+    .collect();
+
+  for _ in &matched {
+    record_matched(&sub.subscription_id);
+  }
+
+  route_events(sub, matched).await;
+}
+
+/// Deliver events buffered while the subscription was paused, they are matched already
+pub async fn release_paused_events(sub: &SubscriptionInfo, events: Vec<Event>) {
+  route_events(sub, events).await;
+}
+
+async fn route_events(sub: &SubscriptionInfo, events: Vec<Event>) {
+  let mut transaction_groups = TransactionGroups::default();
+
+  for event in &events {
+    route_event(sub, event, &mut transaction_groups).await;
+  }
+
+  deliver_transaction_groups(transaction_groups).await;
+}
+
+// all logs of the batch are known by now, so every transaction group is complete
+async fn deliver_transaction_groups(transaction_groups: TransactionGroups) {
+  for (sub, events) in transaction_groups.into_groups() {
+    deliver_event(&sub, &events[0], Some(transaction_logs(&events))).await;
+  }
+}
+
+fn chain_subscriptions(chain_id: u32) -> Vec<SubscriptionInfo> {
+  crate::STATE.with(|state| {
     let subs = state.borrow();
     subs
       .subscriptions
       .values()
-      .filter(|sub| sub.chain_id == event.chain_id)
+      .filter(|sub| sub.chain_id == chain_id)
       .cloned()
       .collect::<Vec<_>>()
-  });
-  // This amount is a minimum required for subscriber to have, otherwise event won't be sent
-  // Estimate the cycles required per event notification
-  let event_size = std::mem::size_of::<EventNotification>(); // Estimate the size of EventNotification in bytes
-  let estimated_cycles_for_event = estimate_cycles_for_event_notification(event_size);
+  })
+}
+
+/// Distribute event to corresponding subscribers and handle sending errors
+async fn distribute_event(event: Event, transaction_groups: &mut TransactionGroups) {
+  // Get all subscriptions for the event's chain_id
+  let subscriptions = chain_subscriptions(event.chain_id);
 
   // Check each subscription and send a notification if the event matches the filter
   for sub in subscriptions {
-    let filter = &sub.filter;
-    if event_matches_filter(&event, filter) {
-      let subscriber_principal = sub.subscriber_principal;
-
-      // Generate a unique notification ID
-      let notification_id = NEXT_NOTIFICATION_ID.with(|id| {
-        let mut id = id.borrow_mut();
-        let current_id = id.clone();
-        *id += Nat::from(1u32);
-        current_id
+    if !event_matches_filter(&event, &sub.filter) || !is_within_expiry(&sub, &event) {
+      continue;
+    }
+
+    match &sub.status {
+      SubscriptionStatus::Active => {
+        record_matched(&sub.subscription_id);
+        route_event(&sub, &event, transaction_groups).await
+      }
+      SubscriptionStatus::Paused {
+        mode: PauseMode::Buffer,
+        ..
+      } => {
+        record_matched(&sub.subscription_id);
+        if buffer_paused_event(&sub.subscription_id, &event) {
+          record_skipped(&sub.subscription_id, 1);
+        }
+      }
+      // the log was fetched for other subscriptions, this one gets it with the backfill on resume
+      SubscriptionStatus::Paused {
+        mode: PauseMode::Exclude,
+        ..
+      } => {}
+      SubscriptionStatus::Suspended { .. } | SubscriptionStatus::LowBalance { .. } => {}
+    }
+  }
+}
+
+async fn route_event(sub: &SubscriptionInfo, event: &Event, transaction_groups: &mut TransactionGroups) {
+  match &sub.delivery {
+    DeliveryMode::Immediate => deliver_event(sub, event, None).await,
+    // delivered (and charged) later, once the coalescing window is closed
+    DeliveryMode::Coalesce(config) => {
+      if buffer_event(&sub.subscription_id, config, event) {
+        record_skipped(&sub.subscription_id, 1);
+      }
+    }
+    // delivered after the whole batch is distributed
+    DeliveryMode::ByTransaction => transaction_groups.push(sub, event),
+  }
+}
+
+/// Deliver the latest buffered events of coalescing subscriptions whose window has closed
+pub async fn flush_coalesced_events(chain_id: u32, current_block: Option<Nat>) {
+  for sub in chain_subscriptions(chain_id)
+    .into_iter()
+    .filter(SubscriptionInfo::is_active)
+  {
+    let DeliveryMode::Coalesce(config) = &sub.delivery else {
+      continue;
+    };
+
+    for event in take_due_events(&sub.subscription_id, config, current_block.as_ref()) {
+      deliver_event(&sub, &event, None).await;
+    }
+  }
+}
+
+/// Send a single notification to the subscriber and charge for it.
+/// For transaction groups `event` is the first log of the transaction.
+async fn deliver_event(sub: &SubscriptionInfo, event: &Event, transaction: Option<TransactionLogs>) {
+  // the subscription could have been cancelled or suspended while delivering previous events of the batch
+  let is_active = crate::STATE.with(|state| {
+    state
+      .borrow()
+      .subscriptions
+      .get(&sub.subscription_id)
+      .is_some_and(SubscriptionInfo::is_active)
+  });
+  if !is_active {
+    return;
+  }
+
+  // This amount is a minimum required for subscriber to have, otherwise event won't be sent
+  let estimated_cycles_for_event = notification_cost_estimate(event.chain_id);
+
+  let subscriber_principal = sub.subscriber_principal;
+  let events_count = transaction
+    .as_ref()
+    .map_or(1, |transaction| transaction.logs.len() as u64);
+
+  let notification_id = next_notification_id();
+
+  let notification = EventNotification {
+    sub_id: sub.subscription_id.clone(),
+    // the id of the (first) event, stable across redelivery
+    event_id: event.id.clone(),
+    notification_id: notification_id.clone(),
+    timestamp: timestamp_nanos(),
+    chain_id: event.chain_id,
+    source: ic_cdk::api::id(),
+    filter: None,
+    log_entry: event.log_entry.clone(),
+    transaction,
+    memo: sub.memo.clone(),
+    labels: sub.labels.clone(),
+  };
+
+  // Check if the subscriber has sufficient balance, otherwise - suspend until a top-up
+  if !Balances::is_sufficient(subscriber_principal, Nat::from(estimated_cycles_for_event)).unwrap() {
+    log_with_metrics!(
+      "Insufficient balance for subscriber, suspending subscription {}: {}",
+      sub.subscription_id,
+      subscriber_principal
+    );
+
+    record_skipped(&sub.subscription_id, events_count);
+
+    if let Some(deadline) = start_grace_period(&sub.subscription_id) {
+      notify_owner(sub, SubscriptionNoticeKind::LowBalance { deadline }).await;
+    }
+
+    return;
+  }
+
+  // the subscriber pays, but the notification can be routed to another canister and method
+  let (target, method) = match &sub.callback {
+    Some(callback) => (callback.canister, Some(callback.method.clone())),
+    None => (sub.subscriber_principal, None),
+  };
+
+  // the subscriber is charged for the arguments actually sent
+  let payload = Encode!(&target, &notification, &method).unwrap();
+  let cycles_for_event = delivery_price(
+    &get_pricing(),
+    event.chain_id,
+    notification_cost(payload.len()),
+    events_count,
+  );
+
+  // Send the notification to the subscriber via proxy canister
+  let call_result: Result<(SendNotificationResult,), _> =
+    call_raw(get_state_value!(proxy_canister), "send_notification", &payload, 0)
+      .await
+      .map_err(|e| format!("Failed to send notification: {:?}", e))
+      .and_then(|reply| {
+        Decode!(&reply, SendNotificationResult)
+          .map(|result| (result,))
+          .map_err(|e| format!("Failed to decode send_notification reply: {:?}", e))
       });
 
-      let notification = EventNotification {
-        sub_id: sub.subscription_id.clone(),
-        event_id: notification_id.clone(),
-        timestamp: timestamp_nanos(),
-        chain_id: event.chain_id,
-        source: ic_cdk::api::id(),
-        filter: None,
-        log_entry: event.log_entry.clone(),
-      };
-
-      // Check if the subscriber has sufficient balance, otherwise - remove the subscription filter
-      if !Balances::is_sufficient(subscriber_principal, Nat::from(estimated_cycles_for_event)).unwrap() {
-        log_with_metrics!(
-          "Insufficient balance for subscriber, unsubscribe: {}",
-          subscriber_principal
-        );
+  match call_result {
+    Ok((send_result,)) => match send_result {
+      SendNotificationResult::Ok => {
+        // if notification was successfully sent - charge this subscriber
 
-        // remove from subscriptions state
-        crate::STATE.with(|subs| {
-          subs.borrow_mut().subscriptions.remove(&sub.subscription_id);
-        });
+        if Balances::is_sufficient(subscriber_principal, Nat::from(cycles_for_event)).unwrap() {
+          Balances::reduce(&subscriber_principal, Nat::from(cycles_for_event)).unwrap();
+          record_charged(&sub.subscription_id, Nat::from(cycles_for_event));
+          // a notification carries logs of a single block
+          let block = event.log_entry.block_number.clone().map(Nat::from);
+          record_balance_change(
+            subscriber_principal,
+            BalanceChangeKind::DeliveryCharge,
+            Nat::from(cycles_for_event),
+            ChargeReference {
+              subscription_id: Some(sub.subscription_id.clone()),
+              chain_id: Some(event.chain_id),
+              from_block: block.clone(),
+              to_block: block,
+            },
+          );
+        }
+        record_delivered(&sub.subscription_id, events_count);
 
-        FILTERS_MANAGER.with(|filter_manager| {
-          let mut filter_manager = filter_manager.borrow_mut();
-          filter_manager.remove_filter(sub.chain_id, &sub.filter);
-        });
+        log_with_metrics!(
+          "Notification sent successfully. ID: {}, Charged: {}",
+          notification_id,
+          cycles_for_event
+        );
 
-        continue;
+        count_delivery(sub).await;
       }
+      SendNotificationResult::Err(error) => {
+        count_failure(sub, events_count, format!("{:?}", error)).await;
 
-      // Send the notification to the subscriber via proxy canister
-      let call_result: Result<(SendNotificationResult,), _> = call(
-        get_state_value!(proxy_canister),
-        "send_notification",
-        (sub.subscriber_principal, notification.clone()),
-      )
-      .await
-      .map_err(|e| format!("Failed to send notification: {:?}", e));
-
-      match call_result {
-        Ok((send_result,)) => match send_result {
-          SendNotificationResult::Ok => {
-            // if notification was successfully sent - charge this subscriber
-
-            if Balances::is_sufficient(subscriber_principal, Nat::from(estimated_cycles_for_event)).unwrap() {
-              Balances::reduce(&subscriber_principal, Nat::from(estimated_cycles_for_event)).unwrap();
-            }
-
-            log_with_metrics!(
-              "Notification sent successfully. ID: {}, Charged: {}",
-              notification_id,
-              estimated_cycles_for_event
-            );
+        // Handle application-level error
+        match error {
+          SendNotificationError::FailedToSend => {
+            log_with_metrics!("Failed to send notification to subscriber.");
           }
-          SendNotificationResult::Err(error) => {
-            // Handle application-level error
-            match error {
-              SendNotificationError::FailedToSend => {
-                log_with_metrics!("Failed to send notification to subscriber.");
-              }
-              SendNotificationError::InvalidSubscriber => {
-                log_with_metrics!("Invalid subscriber principal provided.");
-              }
-            }
+          SendNotificationError::InvalidSubscriber => {
+            log_with_metrics!("Invalid subscriber principal provided.");
           }
-        },
-        Err(transport_error) => {
-          // Handle transport or call-level error
-          log_with_metrics!("Error calling send_notification: {}", transport_error);
         }
       }
+    },
+    Err(transport_error) => {
+      count_failure(sub, events_count, transport_error.clone()).await;
+
+      // Handle transport or call-level error
+      log_with_metrics!("Error calling send_notification: {}", transport_error);
+    }
+  }
+}
+
+/// Suspend the subscription once its subscriber failed `failure_threshold` times in a row
+async fn count_failure(sub: &SubscriptionInfo, events_count: u64, error: String) {
+  let consecutive_failures = record_failed(&sub.subscription_id, events_count, error);
+
+  if consecutive_failures >= sub.failure_threshold && suspend_subscription(&sub.subscription_id, consecutive_failures) {
+    log_with_metrics!(
+      "Subscription suspended after {} failed deliveries: ID={}",
+      consecutive_failures,
+      sub.subscription_id
+    );
+    notify_owner(sub, SubscriptionNoticeKind::Suspended).await;
+  }
+}
+
+/// Cancel a subscription limited by `max_events` once the last notification is delivered
+async fn count_delivery(sub: &SubscriptionInfo) {
+  let remaining_events = crate::STATE.with(|state| {
+    let mut state = state.borrow_mut();
+    let remaining = state
+      .subscriptions
+      .get_mut(&sub.subscription_id)?
+      .remaining_events
+      .as_mut()?;
+    *remaining = remaining.saturating_sub(1);
+    Some(*remaining)
+  });
+
+  if remaining_events == Some(0) && remove_subscription(&sub.subscription_id).is_some() {
+    log_with_metrics!("Subscription reached its event limit: ID={}", sub.subscription_id);
+    notify_owner(sub, SubscriptionNoticeKind::EventLimitReached).await;
+  }
+}
+
+/// Tell the owner about a change of the subscription itself, via proxy canister
+pub async fn notify_owner(sub: &SubscriptionInfo, kind: SubscriptionNoticeKind) {
+  let notice = SubscriptionNotice {
+    sub_id: sub.subscription_id.clone(),
+    timestamp: timestamp_nanos(),
+    chain_id: sub.chain_id,
+    kind,
+  };
+
+  let call_result: Result<(SendNotificationResult,), _> = call(
+    get_state_value!(proxy_canister),
+    "send_subscription_notice",
+    (sub.owner, notice),
+  )
+  .await;
+
+  match call_result {
+    Ok((SendNotificationResult::Ok,)) => {}
+    Ok((SendNotificationResult::Err(error),)) => {
+      log_with_metrics!("Failed to send notice to owner {}: {:?}", sub.owner, error);
+    }
+    Err(err) => {
+      log_with_metrics!("Error calling send_subscription_notice: {:?}", err);
     }
   }
 }
+
+/// Drawn from the same counter as the ids of events without a position in the chain.
+pub fn next_notification_id() -> Nat {
+  NEXT_NOTIFICATION_ID.with(|id| {
+    let mut id = id.borrow_mut();
+    let current_id = id.clone();
+    *id += Nat::from(1u32);
+    current_id
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_notification_cost_grows_with_payload() {
+    let empty = notification_cost(0);
+
+    assert_eq!(empty, BASE_CALL_CYCLES + OK_RESP_SIZE * CYCLES_PER_BYTE_RECEIVE);
+    assert_eq!(notification_cost(100) - empty, 100 * CYCLES_PER_BYTE_SEND);
+  }
+
+  #[test]
+  fn test_notification_ids_are_unique() {
+    let first = next_notification_id();
+
+    assert_eq!(next_notification_id(), first.clone() + 1u32);
+    assert_eq!(next_notification_id(), first + 2u32);
+  }
+}
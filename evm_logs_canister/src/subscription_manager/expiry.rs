@@ -0,0 +1,89 @@
+use candid::Nat;
+use evm_logs_types::{Event, Expiry, SubscriptionInfo, SubscriptionNoticeKind, SubscriptionStatus};
+
+use super::{events_publisher::notify_owner, subscription::remove_subscription};
+use crate::{STATE, internals::misc::timestamp_nanos, log_with_metrics};
+
+/// Whether the expiry has passed once `current_block` is processed.
+fn is_expired(expiry: &Expiry, now: u64, current_block: Option<&Nat>) -> bool {
+  match expiry {
+    Expiry::AtTime(time) => now >= *time,
+    Expiry::AtBlock(block) => current_block.is_some_and(|current| current >= block),
+  }
+}
+
+/// Logs of blocks after an `Expiry::AtBlock` are not delivered, even if fetched in the same batch.
+pub fn is_within_expiry(sub: &SubscriptionInfo, event: &Event) -> bool {
+  match (&sub.expiry, &event.log_entry.block_number) {
+    (Some(Expiry::AtBlock(last_block)), Some(block)) => Nat::from(block.clone()) <= *last_block,
+    _ => true,
+  }
+}
+
+/// Why the subscription has to be cancelled now, if at all
+fn cancellation_reason(
+  sub: &SubscriptionInfo,
+  now: u64,
+  current_block: Option<&Nat>,
+) -> Option<SubscriptionNoticeKind> {
+  if sub
+    .expiry
+    .as_ref()
+    .is_some_and(|expiry| is_expired(expiry, now, current_block))
+  {
+    return Some(SubscriptionNoticeKind::Expired);
+  }
+
+  match sub.status {
+    SubscriptionStatus::LowBalance { deadline } if now >= deadline => Some(SubscriptionNoticeKind::BalanceExhausted),
+    _ => None,
+  }
+}
+
+/// Cancel expired subscriptions of the chain and ones not topped up in time, notify their owners
+pub async fn expire_subscriptions(chain_id: u32, current_block: Option<Nat>) {
+  let now = timestamp_nanos();
+
+  let cancelled: Vec<(SubscriptionInfo, SubscriptionNoticeKind)> = STATE.with(|state| {
+    state
+      .borrow()
+      .subscriptions
+      .values()
+      .filter(|sub| sub.chain_id == chain_id)
+      .filter_map(|sub| cancellation_reason(sub, now, current_block.as_ref()).map(|reason| (sub.clone(), reason)))
+      .collect()
+  });
+
+  for (sub, reason) in cancelled {
+    if remove_subscription(&sub.subscription_id).is_some() {
+      log_with_metrics!(
+        "Subscription cancelled: ID={}, reason: {:?}",
+        sub.subscription_id,
+        reason
+      );
+      notify_owner(&sub, reason).await;
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_time_expiry() {
+    let expiry = Expiry::AtTime(100);
+
+    assert!(!is_expired(&expiry, 99, None));
+    assert!(is_expired(&expiry, 100, None));
+  }
+
+  #[test]
+  fn test_block_expiry_waits_for_the_block_to_be_processed() {
+    let expiry = Expiry::AtBlock(Nat::from(10u32));
+
+    assert!(!is_expired(&expiry, u64::MAX, None));
+    assert!(!is_expired(&expiry, 0, Some(&Nat::from(9u32))));
+    assert!(is_expired(&expiry, 0, Some(&Nat::from(10u32))));
+  }
+}
@@ -0,0 +1,176 @@
+use std::collections::HashMap;
+
+use candid::Nat;
+use evm_logs_types::{Event, SubscriptionInfo, TransactionLogs};
+
+/// Matched events of `DeliveryMode::ByTransaction` subscriptions, collected during one publishing round.
+#[derive(Default)]
+pub struct TransactionGroups {
+  groups: Vec<(SubscriptionInfo, Vec<Event>)>,
+  index: HashMap<(Nat, Vec<u8>), usize>,
+}
+
+impl TransactionGroups {
+  /// Adds the event to the group of its transaction. Events without a transaction hash
+  /// (pending or injected ones) are not grouped with anything.
+  pub fn push(&mut self, sub: &SubscriptionInfo, event: &Event) {
+    let group = event
+      .log_entry
+      .transaction_hash
+      .as_ref()
+      .and_then(|hash| self.index.get(&(sub.subscription_id.clone(), hash.as_ref().to_vec())));
+
+    match group {
+      Some(&position) => self.groups[position].1.push(event.clone()),
+      None => {
+        if let Some(hash) = &event.log_entry.transaction_hash {
+          self
+            .index
+            .insert((sub.subscription_id.clone(), hash.as_ref().to_vec()), self.groups.len());
+        }
+        self.groups.push((sub.clone(), vec![event.clone()]));
+      }
+    }
+  }
+
+  /// Groups in order of their first event, logs of each group ordered by `log_index`.
+  pub fn into_groups(self) -> Vec<(SubscriptionInfo, Vec<Event>)> {
+    self
+      .groups
+      .into_iter()
+      .map(|(sub, mut events)| {
+        events.sort_by_key(|event| event.log_entry.log_index.clone().map(Nat::from));
+        (sub, events)
+      })
+      .collect()
+  }
+}
+
+/// Block context of the group taken from its first log.
+pub fn transaction_logs(events: &[Event]) -> TransactionLogs {
+  let first = &events[0].log_entry;
+
+  TransactionLogs {
+    transaction_hash: first.transaction_hash.clone(),
+    transaction_index: first.transaction_index.clone(),
+    block_number: first.block_number.clone(),
+    block_hash: first.block_hash.clone(),
+    logs: events.iter().map(|event| event.log_entry.clone()).collect(),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::str::FromStr;
+
+  use candid::Principal;
+  use evm_logs_types::{DeliveryMode, Filter};
+  use evm_rpc_types::{Hex, Hex20, Hex32, LogEntry};
+
+  use super::*;
+  use crate::constants::DEFAULT_FAILURE_THRESHOLD;
+
+  const ADDR1_HEX20: &str = "0xd42AcA6E135D1dae6317e776F7EB96Eb91b8eb91";
+
+  fn create_sub(id: u32) -> SubscriptionInfo {
+    SubscriptionInfo {
+      subscription_id: Nat::from(id),
+      subscriber_principal: Principal::anonymous(),
+      owner: Principal::anonymous(),
+      managers: vec![],
+      chain_id: 1,
+      filter: Filter {
+        address: Hex20::from_str(ADDR1_HEX20).unwrap(),
+        topics: None,
+        predicate: None,
+        data_masks: None,
+      },
+      stats: vec![],
+      delivery: DeliveryMode::ByTransaction,
+      status: Default::default(),
+      expiry: None,
+      remaining_events: None,
+      memo: None,
+      labels: vec![],
+      callback: None,
+      failure_threshold: DEFAULT_FAILURE_THRESHOLD,
+    }
+  }
+
+  fn create_event(tx: Option<u8>, log_index: u32) -> Event {
+    Event {
+      id: Nat::from(log_index),
+      timestamp: 0,
+      chain_id: 1,
+      log_entry: LogEntry {
+        address: Hex20::from_str(ADDR1_HEX20).unwrap(),
+        topics: vec![],
+        data: Hex::from(vec![]),
+        block_number: Some(100u32.into()),
+        transaction_hash: tx.map(|tx| Hex32::from([tx; 32])),
+        transaction_index: tx.map(|tx| tx.into()),
+        block_hash: None,
+        log_index: Some(log_index.into()),
+        removed: false,
+      },
+    }
+  }
+
+  fn log_indexes(events: &[Event]) -> Vec<Nat> {
+    events
+      .iter()
+      .map(|event| Nat::from(event.log_entry.log_index.clone().unwrap()))
+      .collect()
+  }
+
+  #[test]
+  fn test_groups_logs_of_same_transaction() {
+    let sub = create_sub(1);
+    let mut groups = TransactionGroups::default();
+    for event in [
+      create_event(Some(1), 7),
+      create_event(Some(2), 3),
+      create_event(Some(1), 5),
+      create_event(Some(1), 6),
+    ] {
+      groups.push(&sub, &event);
+    }
+
+    let groups = groups.into_groups();
+    assert_eq!(groups.len(), 2);
+    assert_eq!(
+      log_indexes(&groups[0].1),
+      vec![Nat::from(5u32), Nat::from(6u32), Nat::from(7u32)]
+    );
+    assert_eq!(log_indexes(&groups[1].1), vec![Nat::from(3u32)]);
+  }
+
+  #[test]
+  fn test_groups_are_per_subscription() {
+    let mut groups = TransactionGroups::default();
+    groups.push(&create_sub(1), &create_event(Some(1), 1));
+    groups.push(&create_sub(2), &create_event(Some(1), 1));
+
+    assert_eq!(groups.into_groups().len(), 2);
+  }
+
+  #[test]
+  fn test_events_without_transaction_hash_are_not_grouped() {
+    let sub = create_sub(1);
+    let mut groups = TransactionGroups::default();
+    groups.push(&sub, &create_event(None, 1));
+    groups.push(&sub, &create_event(None, 2));
+
+    assert_eq!(groups.into_groups().len(), 2);
+  }
+
+  #[test]
+  fn test_transaction_logs_context() {
+    let events = [create_event(Some(4), 1), create_event(Some(4), 2)];
+    let transaction = transaction_logs(&events);
+
+    assert_eq!(transaction.transaction_hash, Some(Hex32::from([4; 32])));
+    assert_eq!(transaction.block_number, Some(100u32.into()));
+    assert_eq!(transaction.logs.len(), 2);
+  }
+}
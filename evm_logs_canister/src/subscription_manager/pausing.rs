@@ -0,0 +1,68 @@
+use candid::Nat;
+use evm_logs_types::Event;
+
+use crate::{STATE, constants::MAX_PAUSED_EVENTS};
+
+/// Keeps the event for a subscription paused in `PauseMode::Buffer`, dropping the oldest one when full.
+/// Returns `true` if an event was dropped.
+pub fn buffer_paused_event(sub_id: &Nat, event: &Event) -> bool {
+  STATE.with(|state| {
+    let mut state = state.borrow_mut();
+    let buffer = state.paused_buffers.entry(sub_id.clone()).or_default();
+
+    buffer.push_back(event.clone());
+    buffer.len() > MAX_PAUSED_EVENTS && buffer.pop_front().is_some()
+  })
+}
+
+pub fn take_paused_events(sub_id: &Nat) -> Vec<Event> {
+  STATE.with(|state| {
+    state
+      .borrow_mut()
+      .paused_buffers
+      .remove(sub_id)
+      .map(Vec::from)
+      .unwrap_or_default()
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use std::str::FromStr;
+
+  use evm_rpc_types::{Hex, Hex20, LogEntry};
+
+  use super::*;
+
+  fn create_event(id: usize) -> Event {
+    Event {
+      id: Nat::from(id),
+      timestamp: 0,
+      chain_id: 1,
+      log_entry: LogEntry {
+        address: Hex20::from_str("0xd42AcA6E135D1dae6317e776F7EB96Eb91b8eb91").unwrap(),
+        topics: vec![],
+        data: Hex::from(vec![]),
+        block_number: None,
+        transaction_hash: None,
+        transaction_index: None,
+        block_hash: None,
+        log_index: None,
+        removed: false,
+      },
+    }
+  }
+
+  #[test]
+  fn test_paused_buffer_drops_oldest_events() {
+    let sub_id = Nat::from(1u32);
+    for id in 0..MAX_PAUSED_EVENTS + 2 {
+      buffer_paused_event(&sub_id, &create_event(id));
+    }
+
+    let events = take_paused_events(&sub_id);
+    assert_eq!(events.len(), MAX_PAUSED_EVENTS);
+    assert_eq!(events[0].id, Nat::from(2u32));
+    assert!(take_paused_events(&sub_id).is_empty());
+  }
+}
@@ -1,35 +1,87 @@
+use std::ops::Bound;
+
 use candid::{Nat, Principal};
-use evm_logs_types::{Filter, SubscriptionInfo};
+use evm_logs_types::{Filter, SubscriptionInfo, SubscriptionsPage, SubscriptionsQuery};
 use evm_rpc_types::{Hex20, Hex32};
 
-use crate::FILTERS_MANAGER;
+use super::stats::with_stats;
+use crate::{
+  FILTERS_MANAGER,
+  constants::{DEFAULT_SUBSCRIPTIONS_PAGE_SIZE, MAX_SUBSCRIPTIONS_PAGE_SIZE},
+};
 
+/// Matching subscriptions after `from_id`, the largest page `query_subscriptions` returns at most
 pub fn get_subscriptions_info(
   chain_id: Option<u32>,
   from_id: Option<Nat>,
   filters: Option<Vec<Filter>>,
 ) -> Vec<SubscriptionInfo> {
-  let mut subs_vec = crate::STATE.with(|state| state.borrow().subscriptions.values().cloned().collect::<Vec<_>>());
+  query_subscriptions(SubscriptionsQuery {
+    chain_id,
+    from_id,
+    filters,
+    limit: Some(MAX_SUBSCRIPTIONS_PAGE_SIZE),
+    ..Default::default()
+  })
+  .subscriptions
+}
 
-  if let Some(ns) = chain_id {
-    subs_vec.retain(|sub| sub.chain_id == ns);
-  }
+fn matches_query(sub: &SubscriptionInfo, query: &SubscriptionsQuery) -> bool {
+  query.chain_id.is_none_or(|chain_id| sub.chain_id == chain_id)
+    && query.owner.is_none_or(|owner| sub.owner == owner)
+    && query
+      .address
+      .as_ref()
+      .is_none_or(|address| sub.filter.address == *address)
+    && query
+      .filters
+      .as_ref()
+      .is_none_or(|filters| filters.contains(&sub.filter))
+    && query.topic.as_ref().is_none_or(|topic| {
+      sub
+        .filter
+        .topics
+        .iter()
+        .flatten()
+        .any(|position| position.contains(topic))
+    })
+}
 
-  if let Some(prev_id) = from_id {
-    if let Some(pos) = subs_vec.iter().position(|sub| sub.subscription_id == prev_id) {
-      if pos + 1 < subs_vec.len() {
-        subs_vec = subs_vec.split_off(pos + 1);
-      } else {
-        subs_vec.clear();
-      }
-    } else {
-      subs_vec.clear();
-    }
-  }
+/// One page of the subscriptions matching the query, in order of their ids
+pub fn query_subscriptions(query: SubscriptionsQuery) -> SubscriptionsPage {
+  let limit = query
+    .limit
+    .unwrap_or(DEFAULT_SUBSCRIPTIONS_PAGE_SIZE)
+    .clamp(1, MAX_SUBSCRIPTIONS_PAGE_SIZE) as usize;
+  let lower_bound = match &query.from_id {
+    Some(from_id) => Bound::Excluded(from_id.clone()),
+    None => Bound::Unbounded,
+  };
 
-  let _ = filters;
+  // one more than the limit to know whether there is a next page
+  let mut subscriptions: Vec<SubscriptionInfo> = crate::STATE.with(|state| {
+    state
+      .borrow()
+      .subscriptions
+      .range((lower_bound, Bound::Unbounded))
+      .map(|(_, sub)| sub)
+      .filter(|sub| matches_query(sub, &query))
+      .take(limit + 1)
+      .cloned()
+      .collect()
+  });
 
-  subs_vec
+  let next_from_id = if subscriptions.len() > limit {
+    subscriptions.truncate(limit);
+    subscriptions.last().map(|sub| sub.subscription_id.clone())
+  } else {
+    None
+  };
+
+  SubscriptionsPage {
+    subscriptions: subscriptions.into_iter().map(with_stats).collect(),
+    next_from_id,
+  }
 }
 
 pub fn get_active_filters() -> Vec<Filter> {
@@ -51,19 +103,132 @@ pub fn get_active_addresses_and_topics(chain_id: u32) -> (Vec<Hex20>, Option<Vec
   })
 }
 
+/// Subscriptions the caller receives notifications for, owns or manages.
 pub fn get_user_subscriptions(caller: Principal) -> Vec<SubscriptionInfo> {
-  let subscription_ids = crate::STATE.with(|state| {
+  crate::STATE.with(|state| {
     state
       .borrow()
-      .subscribers
-      .get(&caller)
+      .subscriptions
+      .values()
+      .filter(|sub| sub.subscriber_principal == caller || sub.is_manageable_by(&caller))
       .cloned()
-      .unwrap_or_else(Vec::new)
-  });
-  crate::STATE.with(|state| {
-    subscription_ids
-      .iter()
-      .filter_map(|id| state.borrow().subscriptions.get(id).cloned())
+      .map(with_stats)
       .collect()
   })
 }
+
+#[cfg(test)]
+mod tests {
+  use std::str::FromStr;
+
+  use super::*;
+  use crate::constants::DEFAULT_FAILURE_THRESHOLD;
+
+  const ADDR1_HEX20: &str = "0xd42AcA6E135D1dae6317e776F7EB96Eb91b8eb91";
+  const ADDR2_HEX20: &str = "0xDA2efffa45cf5D960209aA0921Cf42a4a2a085cf";
+  const TOPIC1_HEX32: &str = "0x4d69d0bd4287b7f66c548f90154dc81bc98f65a1b362775df5ae171a2ccd262b";
+
+  fn insert_subscription(id: u32, address: &str, topic: Option<&str>, owner: u8) {
+    let subscription = SubscriptionInfo {
+      subscription_id: Nat::from(id),
+      subscriber_principal: Principal::from_slice(&[owner]),
+      owner: Principal::from_slice(&[owner]),
+      managers: vec![],
+      chain_id: 1,
+      filter: Filter {
+        address: Hex20::from_str(address).unwrap(),
+        topics: topic.map(|topic| vec![vec![Hex32::from_str(topic).unwrap()]]),
+        predicate: None,
+        data_masks: None,
+      },
+      stats: vec![],
+      delivery: Default::default(),
+      status: Default::default(),
+      expiry: None,
+      remaining_events: None,
+      memo: None,
+      labels: vec![],
+      callback: None,
+      failure_threshold: DEFAULT_FAILURE_THRESHOLD,
+    };
+    crate::STATE.with(|state| {
+      state.borrow_mut().subscriptions.insert(Nat::from(id), subscription);
+    });
+  }
+
+  fn ids(page: &SubscriptionsPage) -> Vec<Nat> {
+    page
+      .subscriptions
+      .iter()
+      .map(|sub| sub.subscription_id.clone())
+      .collect()
+  }
+
+  #[test]
+  fn test_pages_are_ordered_by_id() {
+    for id in [5, 1, 3, 2, 4] {
+      insert_subscription(id, ADDR1_HEX20, None, 1);
+    }
+
+    let query = |from_id| SubscriptionsQuery {
+      from_id,
+      limit: Some(2),
+      ..Default::default()
+    };
+
+    let first = query_subscriptions(query(None));
+    assert_eq!(ids(&first), vec![Nat::from(1u32), Nat::from(2u32)]);
+
+    let second = query_subscriptions(query(first.next_from_id));
+    assert_eq!(ids(&second), vec![Nat::from(3u32), Nat::from(4u32)]);
+
+    let last = query_subscriptions(query(second.next_from_id));
+    assert_eq!(ids(&last), vec![Nat::from(5u32)]);
+    assert!(last.next_from_id.is_none());
+  }
+
+  #[test]
+  fn test_legacy_query_returns_one_page() {
+    let count = MAX_SUBSCRIPTIONS_PAGE_SIZE + DEFAULT_SUBSCRIPTIONS_PAGE_SIZE;
+    for id in 1..=count {
+      insert_subscription(id, ADDR1_HEX20, None, 1);
+    }
+
+    let first = get_subscriptions_info(None, None, None);
+    assert_eq!(first.len(), MAX_SUBSCRIPTIONS_PAGE_SIZE as usize);
+
+    let from_id = first.last().map(|sub| sub.subscription_id.clone());
+    let rest = get_subscriptions_info(None, from_id, None);
+    assert_eq!(rest.len(), DEFAULT_SUBSCRIPTIONS_PAGE_SIZE as usize);
+    assert_eq!(rest.last().unwrap().subscription_id, Nat::from(count));
+  }
+
+  #[test]
+  fn test_query_criteria() {
+    insert_subscription(1, ADDR1_HEX20, None, 1);
+    insert_subscription(2, ADDR2_HEX20, Some(TOPIC1_HEX32), 1);
+    insert_subscription(3, ADDR2_HEX20, None, 2);
+
+    let by_address = query_subscriptions(SubscriptionsQuery {
+      address: Some(Hex20::from_str(ADDR2_HEX20).unwrap()),
+      ..Default::default()
+    });
+    assert_eq!(ids(&by_address), vec![Nat::from(2u32), Nat::from(3u32)]);
+
+    let by_topic = query_subscriptions(SubscriptionsQuery {
+      topic: Some(Hex32::from_str(TOPIC1_HEX32).unwrap()),
+      ..Default::default()
+    });
+    assert_eq!(ids(&by_topic), vec![Nat::from(2u32)]);
+
+    let by_owner = query_subscriptions(SubscriptionsQuery {
+      owner: Some(Principal::from_slice(&[2])),
+      ..Default::default()
+    });
+    assert_eq!(ids(&by_owner), vec![Nat::from(3u32)]);
+
+    let filter = by_owner.subscriptions[0].filter.clone();
+    let by_filter = get_subscriptions_info(None, None, Some(vec![filter]));
+    assert_eq!(by_filter.len(), 1);
+  }
+}
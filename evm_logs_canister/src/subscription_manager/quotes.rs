@@ -0,0 +1,145 @@
+use candid::Nat;
+use evm_logs_types::{Filter, QuoteSubscriptionResult, SubscriptionError, SubscriptionQuote};
+
+use super::{events_publisher::notification_cost_estimate, subscription::chain_service, utils::validate_filter};
+use crate::{
+  STATE,
+  chain_service::logs_fetcher::{estimate_fetch_cycles, query_share},
+  internals::misc::generate_chain_configs,
+  types::pricing::{fetch_price, get_pricing},
+};
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Expected daily cost of subscribing to the filter, see `SubscriptionQuote`.
+pub fn quote_subscription(chain_id: u32, filter: Filter) -> QuoteSubscriptionResult {
+  if let Err(err) = validate_filter(&filter) {
+    return QuoteSubscriptionResult::Err(SubscriptionError::ImproperConfig(err));
+  }
+
+  // a chain's service starts with its first subscription, until then its config tells how often it's polled
+  let polling_interval_secs = match chain_service(chain_id) {
+    Some(service) => service.config.monitoring_interval_sec,
+    None => match generate_chain_configs()
+      .into_iter()
+      .find(|config| config.chain_id == chain_id)
+    {
+      Some(config) => config.monitoring_interval_sec,
+      None => {
+        return QuoteSubscriptionResult::Err(SubscriptionError::ImproperConfig(format!(
+          "Chain {} is not supported",
+          chain_id
+        )));
+      }
+    },
+  };
+
+  let (observed_events, observed_polls, fetch_cost_per_share) = STATE.with(|state| {
+    let event_rates = &state.borrow().event_rates;
+    let (events, polls) = event_rates.observed_events(chain_id, &filter);
+    (events, polls, event_rates.fetch_cost_per_share(chain_id))
+  });
+
+  // without a charged fetch of the chain yet, only the request itself can be estimated
+  let polling_cycles = fetch_cost_per_share.map_or_else(
+    || estimate_fetch_cycles(1, filter.topics.as_ref()),
+    |cost| cost.saturating_mul(query_share(&filter)),
+  );
+
+  QuoteSubscriptionResult::Ok(daily_quote(
+    chain_id,
+    polling_interval_secs,
+    (observed_events, observed_polls),
+    fetch_price(&get_pricing(), chain_id, polling_cycles, 1),
+    notification_cost_estimate(chain_id),
+  ))
+}
+
+/// Scales per-poll and per-event prices to a day of polling every `polling_interval_secs`.
+fn daily_quote(
+  chain_id: u32,
+  polling_interval_secs: u64,
+  (observed_events, observed_polls): (u64, u64),
+  price_per_poll: u64,
+  price_per_event: u64,
+) -> SubscriptionQuote {
+  let polls_per_day = SECONDS_PER_DAY / polling_interval_secs.max(1);
+  let events_per_day = if observed_polls == 0 {
+    0
+  } else {
+    (observed_events as u128 * polls_per_day as u128 / observed_polls as u128) as u64
+  };
+
+  let polling_cycles_per_day = Nat::from(price_per_poll as u128 * polls_per_day as u128);
+  let delivery_cycles_per_day = Nat::from(price_per_event as u128 * events_per_day as u128);
+
+  SubscriptionQuote {
+    chain_id,
+    polling_interval_secs,
+    observed_polls,
+    events_per_day,
+    total_cycles_per_day: polling_cycles_per_day.clone() + delivery_cycles_per_day.clone(),
+    polling_cycles_per_day,
+    delivery_cycles_per_day,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::str::FromStr;
+
+  use evm_rpc_types::Hex20;
+
+  use super::*;
+  use crate::constants::ETHEREUM_CHAIN_ID;
+
+  fn create_filter() -> Filter {
+    Filter {
+      address: Hex20::from_str("0xd42AcA6E135D1dae6317e776F7EB96Eb91b8eb91").unwrap(),
+      topics: None,
+      predicate: None,
+      data_masks: None,
+    }
+  }
+
+  #[test]
+  fn test_daily_quote_scales_observed_rate() {
+    // 15s interval: 5_760 polls a day, 3 events every 10 polls
+    let quote = daily_quote(1, 15, (3, 10), 1_000, 50_000);
+
+    assert_eq!(quote.events_per_day, 1_728);
+    assert_eq!(quote.polling_cycles_per_day, Nat::from(5_760_000u64));
+    assert_eq!(quote.delivery_cycles_per_day, Nat::from(86_400_000u64));
+    assert_eq!(quote.total_cycles_per_day, Nat::from(92_160_000u64));
+  }
+
+  #[test]
+  fn test_daily_quote_without_observations() {
+    let quote = daily_quote(1, 60, (0, 0), 1_000, 50_000);
+
+    assert_eq!(quote.observed_polls, 0);
+    assert_eq!(quote.events_per_day, 0);
+    assert_eq!(quote.delivery_cycles_per_day, Nat::from(0u32));
+    assert_eq!(quote.total_cycles_per_day, Nat::from(1_440_000u64));
+  }
+
+  #[test]
+  fn test_quote_for_a_chain_not_monitored_yet() {
+    let interval = generate_chain_configs()
+      .into_iter()
+      .find(|config| config.chain_id == ETHEREUM_CHAIN_ID)
+      .unwrap()
+      .monitoring_interval_sec;
+
+    let QuoteSubscriptionResult::Ok(quote) = quote_subscription(ETHEREUM_CHAIN_ID, create_filter()) else {
+      panic!("no quote for an unmonitored chain");
+    };
+    assert_eq!(quote.polling_interval_secs, interval);
+    assert_eq!(quote.observed_polls, 0);
+
+    assert!(matches!(
+      quote_subscription(u32::MAX, create_filter()),
+      QuoteSubscriptionResult::Err(SubscriptionError::ImproperConfig(_))
+    ));
+  }
+}
@@ -0,0 +1,148 @@
+use candid::Nat;
+use evm_logs_types::{Map, SubscriptionInfo, SubscriptionStats, Value};
+
+use crate::{STATE, internals::misc::timestamp_nanos};
+
+fn update(sub_id: &Nat, f: impl FnOnce(&mut SubscriptionStats)) {
+  STATE.with(|state| f(state.borrow_mut().subscription_stats.entry(sub_id.clone()).or_default()));
+}
+
+pub fn record_matched(sub_id: &Nat) {
+  update(sub_id, |stats| stats.events_matched += 1);
+}
+
+/// `events` is the number of logs in the notification
+pub fn record_delivered(sub_id: &Nat, events: u64) {
+  let now = timestamp_nanos();
+  update(sub_id, |stats| {
+    stats.events_delivered += events;
+    stats.last_delivery_at = Some(now);
+    stats.consecutive_failures = 0;
+  });
+}
+
+/// Returns the number of consecutive failures so far
+pub fn record_failed(sub_id: &Nat, events: u64, error: String) -> u32 {
+  let mut consecutive_failures = 0;
+  update(sub_id, |stats| {
+    stats.events_failed += events;
+    stats.last_error = Some(error);
+    stats.consecutive_failures += 1;
+    consecutive_failures = stats.consecutive_failures;
+  });
+  consecutive_failures
+}
+
+pub fn reset_failures(sub_id: &Nat) {
+  update(sub_id, |stats| stats.consecutive_failures = 0);
+}
+
+pub fn record_skipped(sub_id: &Nat, events: u64) {
+  update(sub_id, |stats| stats.events_skipped += events);
+}
+
+pub fn record_charged(sub_id: &Nat, cycles: Nat) {
+  update(sub_id, |stats| stats.cycles_charged += cycles);
+}
+
+/// `None` if there is no such subscription
+pub fn get_stats(sub_id: &Nat) -> Option<SubscriptionStats> {
+  STATE.with(|state| {
+    let state = state.borrow();
+    state
+      .subscriptions
+      .contains_key(sub_id)
+      .then(|| state.subscription_stats.get(sub_id).cloned().unwrap_or_default())
+  })
+}
+
+pub fn drop_stats(sub_id: &Nat) {
+  STATE.with(|state| {
+    state.borrow_mut().subscription_stats.remove(sub_id);
+  });
+}
+
+fn entry(key: &str, value: Value) -> Map {
+  Map {
+    key: Value::Text(key.to_string()),
+    value,
+  }
+}
+
+/// ICRC-16 form of the statistics, unset optional values are left out
+pub fn stats_to_map(stats: &SubscriptionStats) -> Vec<Map> {
+  let mut map = vec![
+    entry("events_matched", Value::Nat(stats.events_matched.into())),
+    entry("events_delivered", Value::Nat(stats.events_delivered.into())),
+    entry("events_failed", Value::Nat(stats.events_failed.into())),
+    entry("events_skipped", Value::Nat(stats.events_skipped.into())),
+    entry("consecutive_failures", Value::Nat(stats.consecutive_failures.into())),
+    entry(
+      "cycles_charged",
+      Value::Nat(u128::try_from(stats.cycles_charged.0.clone()).unwrap_or(u128::MAX)),
+    ),
+  ];
+
+  if let Some(last_delivery_at) = stats.last_delivery_at {
+    map.push(entry("last_delivery_at", Value::Nat(last_delivery_at.into())));
+  }
+  if let Some(last_error) = &stats.last_error {
+    map.push(entry("last_error", Value::Text(last_error.clone())));
+  }
+
+  map
+}
+
+/// Subscription as returned by queries, with `stats` filled in
+pub fn with_stats(mut sub: SubscriptionInfo) -> SubscriptionInfo {
+  sub.stats = stats_to_map(&get_stats(&sub.subscription_id).unwrap_or_default());
+  sub
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_stats_to_map() {
+    let stats = SubscriptionStats {
+      events_matched: 3,
+      events_delivered: 2,
+      cycles_charged: Nat::from(500u32),
+      last_error: Some("trapped".to_string()),
+      ..Default::default()
+    };
+    let map = stats_to_map(&stats);
+
+    assert_eq!(map.len(), 7);
+    assert!(matches!(&map[1].key, Value::Text(key) if key == "events_delivered"));
+    assert!(matches!(map[1].value, Value::Nat(2)));
+    assert!(matches!(map[5].value, Value::Nat(500)));
+    assert!(matches!(&map[6].value, Value::Text(error) if error == "trapped"));
+  }
+
+  #[test]
+  fn test_counters_accumulate() {
+    let sub_id = Nat::from(1u32);
+    record_matched(&sub_id);
+    record_matched(&sub_id);
+    record_skipped(&sub_id, 1);
+    record_charged(&sub_id, Nat::from(10u32));
+    record_charged(&sub_id, Nat::from(5u32));
+
+    let stats = STATE.with(|state| state.borrow().subscription_stats[&sub_id].clone());
+    assert_eq!(stats.events_matched, 2);
+    assert_eq!(stats.events_skipped, 1);
+    assert_eq!(stats.cycles_charged, Nat::from(15u32));
+  }
+
+  #[test]
+  fn test_delivery_resets_consecutive_failures() {
+    let sub_id = Nat::from(2u32);
+
+    assert_eq!(record_failed(&sub_id, 1, "trapped".to_string()), 1);
+    assert_eq!(record_failed(&sub_id, 1, "trapped".to_string()), 2);
+    reset_failures(&sub_id);
+    assert_eq!(record_failed(&sub_id, 1, "trapped".to_string()), 1);
+  }
+}
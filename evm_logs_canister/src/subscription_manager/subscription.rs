@@ -1,23 +1,119 @@
-use std::rc::Rc;
+use std::{rc::Rc, time::Duration};
 
 use candid::{Nat, Principal};
 use evm_logs_types::{
-  RegisterSubscriptionError, RegisterSubscriptionResult, SubscriptionInfo, SubscriptionRegistration, UnsubscribeResult,
+  Filter, PauseMode, RegisterSubscriptionError, RegisterSubscriptionResult, SubscriptionError, SubscriptionInfo,
+  SubscriptionRegistration, SubscriptionResult, SubscriptionStatus, UnsubscribeResult,
 };
 
+use super::{
+  coalescing::drop_buffer,
+  events_publisher::{notification_cost_estimate, publish_events_to, release_paused_events},
+  expiry::expire_subscriptions,
+  pausing::take_paused_events,
+  stats::{drop_stats, reset_failures},
+  utils::{validate_callback, validate_delivery_mode, validate_filter, validate_metadata},
+  withdrawals::refund_if_unsubscribed,
+};
 use crate::{
-  CHAIN_SERVICES, FILTERS_MANAGER, NEXT_SUBSCRIPTION_ID, chain_service::service::ChainService, get_state_value,
-  internals::misc::generate_chain_configs, log_with_metrics,
+  CHAIN_SERVICES, FILTERS_MANAGER, NEXT_SUBSCRIPTION_ID,
+  chain_service::{
+    events_processor::logs_to_events, logs_fetcher::fetch_logs_range, service::ChainService,
+    utils::calculate_request_chunk_size,
+  },
+  constants::{DEFAULT_FAILURE_THRESHOLD, LOW_BALANCE_GRACE_PERIOD_SECS},
+  get_state_value,
+  internals::misc::{generate_chain_configs, timestamp_nanos},
+  log_with_metrics,
+  types::balances::Balances,
 };
 
 pub fn init() {
   log_with_metrics!("SubscriptionManager initialized");
 }
 
-pub async fn register_subscription(registration: SubscriptionRegistration) -> RegisterSubscriptionResult {
+/// Returns the subscription if `caller` is its owner or one of its managers.
+pub fn authorize(caller: &Principal, subscription_id: &Nat) -> Result<SubscriptionInfo, SubscriptionError> {
+  let subscription = crate::STATE.with(|state| state.borrow().subscriptions.get(subscription_id).cloned());
+
+  match subscription {
+    Some(subscription) if subscription.is_manageable_by(caller) => Ok(subscription),
+    Some(_) => Err(SubscriptionError::Unauthorized),
+    None => Err(SubscriptionError::NotFound(subscription_id.clone())),
+  }
+}
+
+/// Whether the caller may register subscriptions charged to the subscriber: it's the subscriber itself
+/// or one the subscriber delegated to manage any of its subscriptions.
+fn may_charge(caller: &Principal, subscriber: &Principal) -> bool {
+  caller == subscriber
+    || crate::STATE.with(|state| {
+      state
+        .borrow()
+        .subscriptions
+        .values()
+        .any(|sub| sub.subscriber_principal == *subscriber && sub.is_manageable_by(caller))
+    })
+}
+
+/// The caller becomes the owner of the subscription, `canister_to_top_up` is the one paying for
+/// and receiving its notifications.
+pub async fn register_subscription(
+  caller: Principal,
+  registration: SubscriptionRegistration,
+) -> RegisterSubscriptionResult {
+  // nobody could be told apart from anonymous owner
+  if caller == Principal::anonymous() {
+    return RegisterSubscriptionResult::Err(RegisterSubscriptionError::Unauthorized);
+  }
+
   let subscriber_principal = registration.canister_to_top_up;
   let filter = &registration.filter;
 
+  // nobody else can put the balance of the subscriber at stake
+  if !may_charge(&caller, &subscriber_principal) {
+    log_with_metrics!("{} isn't allowed to subscribe for {}", caller, subscriber_principal);
+    return RegisterSubscriptionResult::Err(RegisterSubscriptionError::Unauthorized);
+  }
+
+  if let Err(err) = validate_filter(filter) {
+    log_with_metrics!("Invalid filter for {}: {}", subscriber_principal, err);
+    return RegisterSubscriptionResult::Err(RegisterSubscriptionError::ImproperConfig(err));
+  }
+
+  if registration.failure_threshold == Some(0) {
+    return RegisterSubscriptionResult::Err(RegisterSubscriptionError::ImproperConfig(
+      "failure_threshold must be greater than zero".to_string(),
+    ));
+  }
+
+  if registration.max_events == Some(0) {
+    return RegisterSubscriptionResult::Err(RegisterSubscriptionError::ImproperConfig(
+      "max_events must be greater than zero".to_string(),
+    ));
+  }
+
+  let labels = registration.labels.clone().unwrap_or_default();
+
+  if let Err(err) = validate_metadata(registration.memo.as_ref(), &labels) {
+    return RegisterSubscriptionResult::Err(RegisterSubscriptionError::ImproperConfig(err));
+  }
+
+  if let Some(Err(err)) = registration
+    .callback
+    .as_ref()
+    .map(|callback| validate_callback(callback, &subscriber_principal, &caller))
+  {
+    return RegisterSubscriptionResult::Err(RegisterSubscriptionError::ImproperConfig(err));
+  }
+
+  let delivery = registration.delivery.clone().unwrap_or_default();
+
+  if let Err(err) = validate_delivery_mode(&delivery) {
+    log_with_metrics!("Invalid delivery mode for {}: {}", subscriber_principal, err);
+    return RegisterSubscriptionResult::Err(RegisterSubscriptionError::ImproperConfig(err));
+  }
+
   let subscribers = get_state_value!(subscribers);
   let subscriptions = get_state_value!(subscriptions);
 
@@ -50,9 +146,19 @@ pub async fn register_subscription(registration: SubscriptionRegistration) -> Re
   let subscription_info = SubscriptionInfo {
     subscription_id: sub_id.clone(),
     subscriber_principal,
+    owner: caller,
+    managers: registration.managers.clone().unwrap_or_default(),
     chain_id,
     filter: filter.clone(),
     stats: vec![],
+    delivery,
+    status: SubscriptionStatus::Active,
+    expiry: registration.expiry.clone(),
+    remaining_events: registration.max_events,
+    memo: registration.memo.clone(),
+    labels,
+    callback: registration.callback.clone(),
+    failure_threshold: registration.failure_threshold.unwrap_or(DEFAULT_FAILURE_THRESHOLD),
   };
 
   // add to subscriptions
@@ -111,60 +217,446 @@ pub async fn register_subscription(registration: SubscriptionRegistration) -> Re
       chain_services.borrow_mut().push(service);
     });
   }
+  // the timer may have been stopped with the other subscriptions of the chain left out of fetching
+  sync_chain_monitoring(chain_id);
 
   RegisterSubscriptionResult::Ok(sub_id)
 }
 
 pub fn unsubscribe(caller: Principal, subscription_id: Nat) -> UnsubscribeResult {
+  if let Err(err) = authorize(&caller, &subscription_id) {
+    return UnsubscribeResult::Err(err);
+  }
+
+  match remove_subscription(&subscription_id) {
+    Some(_) => UnsubscribeResult::Ok(),
+    None => UnsubscribeResult::Err(SubscriptionError::NotFound(subscription_id)),
+  }
+}
+
+/// `unsubscribe`, with `refund` hands the remaining balance back to the subscriber if it was its last subscription.
+pub async fn cancel_subscription(caller: Principal, subscription_id: Nat, refund: bool) -> UnsubscribeResult {
+  let subscriber = crate::STATE.with(|state| {
+    state
+      .borrow()
+      .subscriptions
+      .get(&subscription_id)
+      .map(|sub| sub.subscriber_principal)
+  });
+
+  let result = unsubscribe(caller, subscription_id);
+
+  if let (UnsubscribeResult::Ok(), Some(subscriber), true) = (&result, subscriber, refund) {
+    refund_if_unsubscribed(subscriber).await;
+  }
+
+  result
+}
+
+/// Removes the subscription together with its filter and pending events,
+/// stops the chain timer if it was the last fetching subscription of the chain.
+pub fn remove_subscription(subscription_id: &Nat) -> Option<SubscriptionInfo> {
   // remove subscription from the state
-  let removed_subscription = crate::STATE.with(|subs| subs.borrow_mut().subscriptions.remove(&subscription_id));
+  let subscription_info = crate::STATE.with(|subs| subs.borrow_mut().subscriptions.remove(subscription_id))?;
+
+  let subscriber_principal = subscription_info.subscriber_principal;
+  let chain_id = subscription_info.chain_id;
 
-  if let Some(subscription_info) = removed_subscription {
-    let filter = subscription_info.filter;
-    let chain_id = subscription_info.chain_id;
+  // events waiting for the coalescing window or a resume are not delivered anymore
+  drop_buffer(subscription_id);
+  take_paused_events(subscription_id);
+  drop_stats(subscription_id);
 
-    // remove subscription filter from the filter manager
+  // remove subscription filter from the filter manager, unless it's already left out of fetching
+  if subscription_info.is_fetching() {
     FILTERS_MANAGER.with(|manager| {
       let mut manager = manager.borrow_mut();
-      manager.remove_filter(chain_id, &filter);
+      manager.remove_filter(chain_id, &subscription_info.filter);
     });
+  }
 
-    // update subscribers state
-    crate::STATE.with(|subs| {
-      let mut subs = subs.borrow_mut();
-      if let Some(sub_list) = subs.subscribers.get_mut(&caller) {
-        sub_list.retain(|id| *id != subscription_id);
-        if sub_list.is_empty() {
-          subs.subscribers.remove(&caller);
-        }
+  // update subscribers state
+  crate::STATE.with(|subs| {
+    let mut subs = subs.borrow_mut();
+    if let Some(sub_list) = subs.subscribers.get_mut(&subscriber_principal) {
+      sub_list.retain(|id| id != subscription_id);
+      if sub_list.is_empty() {
+        subs.subscribers.remove(&subscriber_principal);
       }
+    }
+  });
+
+  // stop the timer of the chain, but don't remove its service
+  sync_chain_monitoring(chain_id);
+
+  Some(subscription_info)
+}
+
+/// Swaps the filter keeping the subscription id, balance and pending deliveries.
+/// Runs without awaiting, so fetching never sees the subscription without a filter
+/// and the chain timer keeps running.
+pub fn update_filter(caller: Principal, subscription_id: Nat, filter: Filter) -> SubscriptionResult {
+  let subscription = match authorize(&caller, &subscription_id) {
+    Ok(subscription) => subscription,
+    Err(err) => return SubscriptionResult::Err(err),
+  };
+
+  if let Err(err) = validate_filter(&filter) {
+    return SubscriptionResult::Err(SubscriptionError::ImproperConfig(err));
+  }
+
+  if subscription.filter == filter {
+    return SubscriptionResult::Ok;
+  }
+
+  let same_filter_exists = crate::STATE.with(|state| {
+    state.borrow().subscriptions.values().any(|sub| {
+      sub.subscription_id != subscription_id
+        && sub.subscriber_principal == subscription.subscriber_principal
+        && sub.filter == filter
+    })
+  });
+
+  if same_filter_exists {
+    return SubscriptionResult::Err(SubscriptionError::SameFilterExists);
+  }
+
+  replace_filter(&subscription, &filter);
+
+  log_with_metrics!("Subscription updated: ID={}, Filter = {:?}", subscription_id, filter);
+
+  SubscriptionResult::Ok
+}
+
+fn replace_filter(subscription: &SubscriptionInfo, filter: &Filter) {
+  crate::STATE.with(|state| {
+    if let Some(sub) = state.borrow_mut().subscriptions.get_mut(&subscription.subscription_id) {
+      sub.filter = filter.clone();
+    }
+  });
+
+  // a subscription left out of fetching registers the new filter on resume
+  if subscription.is_fetching() {
+    FILTERS_MANAGER.with(|manager| {
+      let mut manager = manager.borrow_mut();
+      manager.add_filter(subscription.chain_id, filter);
+      manager.remove_filter(subscription.chain_id, &subscription.filter);
     });
+  }
+}
 
-    // Stop timer for specific chain ID if there are no more subscriptions for it
-    let has_subscriptions = crate::STATE.with(|subs| {
-      subs
-        .borrow()
-        .subscriptions
-        .values()
-        .any(|sub_info| sub_info.chain_id == chain_id)
+pub fn chain_service(chain_id: u32) -> Option<Rc<ChainService>> {
+  CHAIN_SERVICES.with(|chain_services| {
+    chain_services
+      .borrow()
+      .iter()
+      .rev() // the latest service of the chain is the running one
+      .find(|service| service.config.chain_id == chain_id)
+      .cloned()
+  })
+}
+
+/// Whether any subscription of the chain takes part in fetching.
+fn has_fetching_subscriptions(chain_id: u32) -> bool {
+  crate::STATE.with(|state| {
+    state
+      .borrow()
+      .subscriptions
+      .values()
+      .any(|sub| sub.chain_id == chain_id && sub.is_fetching())
+  })
+}
+
+/// Runs the chain timer only while some subscription of the chain is fetching,
+/// so logs are neither polled nor paid for with all of them suspended, paused or low on balance.
+fn sync_chain_monitoring(chain_id: u32) {
+  let Some(service) = chain_service(chain_id) else {
+    return;
+  };
+
+  let monitoring = service.timer_id.borrow().is_some();
+  match (has_fetching_subscriptions(chain_id), monitoring) {
+    (true, false) => {
+      let interval = Duration::from_secs(service.config.monitoring_interval_sec);
+      service.start_monitoring(interval);
+    }
+    (false, true) => service.stop_monitoring(),
+    _ => {}
+  }
+}
+
+/// Last block processed for the chain, `None` if its monitoring hasn't started yet.
+fn chain_cursor(chain_id: u32) -> Option<Nat> {
+  chain_service(chain_id)
+    .map(|service| service.last_processed_block.borrow().clone())
+    .filter(|block| *block != 0u32)
+}
+
+fn set_status(subscription_id: &Nat, status: SubscriptionStatus) {
+  crate::STATE.with(|state| {
+    if let Some(sub) = state.borrow_mut().subscriptions.get_mut(subscription_id) {
+      sub.status = status;
+    }
+  });
+}
+
+/// Suspends delivery remembering the chain's block cursor, see `PauseMode`.
+pub fn pause_subscription(caller: Principal, subscription_id: Nat, mode: PauseMode) -> SubscriptionResult {
+  let subscription = match authorize(&caller, &subscription_id) {
+    Ok(subscription) => subscription,
+    Err(err) => return SubscriptionResult::Err(err),
+  };
+
+  if !subscription.is_active() {
+    return SubscriptionResult::Err(SubscriptionError::NotActive);
+  }
+
+  if mode == PauseMode::Exclude {
+    FILTERS_MANAGER.with(|manager| {
+      manager
+        .borrow_mut()
+        .remove_filter(subscription.chain_id, &subscription.filter);
     });
+  }
+
+  let at_block = chain_cursor(subscription.chain_id);
+  set_status(&subscription_id, SubscriptionStatus::Paused { mode, at_block });
+  sync_chain_monitoring(subscription.chain_id);
+
+  log_with_metrics!("Subscription paused: ID={}", subscription_id);
+
+  SubscriptionResult::Ok
+}
+
+/// Continues delivery from the block cursor recorded on pause: buffered events are delivered,
+/// excluded subscriptions get the missed blocks fetched for them first.
+pub async fn resume_subscription(caller: Principal, subscription_id: Nat) -> SubscriptionResult {
+  let subscription = match authorize(&caller, &subscription_id) {
+    Ok(subscription) => subscription,
+    Err(err) => return SubscriptionResult::Err(err),
+  };
 
-    if !has_subscriptions {
-      CHAIN_SERVICES.with(|chain_services| {
-        let chain_services = chain_services.borrow_mut();
-        // call stop_monitoring for the chain service, but don't remove it
-        if let Some(service) = chain_services
-          .iter()
-          .find(|service| service.config.chain_id == chain_id)
-        {
-          service.stop_monitoring()
+  let SubscriptionStatus::Paused { mode, at_block } = subscription.status.clone() else {
+    return SubscriptionResult::Err(SubscriptionError::NotPaused);
+  };
+
+  match mode {
+    PauseMode::Buffer => {
+      // activate before any await, so regular fetching covers everything after the current cursor
+      set_status(&subscription_id, SubscriptionStatus::Active);
+      let subscription = SubscriptionInfo {
+        status: SubscriptionStatus::Active,
+        ..subscription
+      };
+      log_with_metrics!("Subscription resumed: ID={}", subscription_id);
+
+      release_paused_events(&subscription, take_paused_events(&subscription_id)).await;
+    }
+    PauseMode::Exclude => {
+      match backfill_excluded(&subscription, at_block).await {
+        Ok(true) => {}
+        // a concurrent resume took over and finishes it
+        Ok(false) => return SubscriptionResult::Ok,
+        Err(err) => {
+          log_with_metrics!("Backfill failed for subscription {}: {}", subscription_id, err);
+          return SubscriptionResult::Err(SubscriptionError::BackfillFailed(err));
         }
+      }
+
+      // caught up with the chain cursor, regular fetching continues without an await in between
+      FILTERS_MANAGER.with(|manager| {
+        manager
+          .borrow_mut()
+          .add_filter(subscription.chain_id, &subscription.filter);
       });
+      set_status(&subscription_id, SubscriptionStatus::Active);
+      sync_chain_monitoring(subscription.chain_id);
+      log_with_metrics!("Subscription resumed: ID={}", subscription_id);
+    }
+  }
+
+  SubscriptionResult::Ok
+}
+
+/// Fetches the blocks an excluded subscription missed, up to the chain cursor, in chunks that fit
+/// into a response. `at_block` advances with every delivered chunk, so after a failure another
+/// `resume_subscription` continues where this one stopped.
+/// Returns `false` if the subscription changed meanwhile, e.g. a concurrent resume or unsubscribe.
+async fn backfill_excluded(subscription: &SubscriptionInfo, at_block: Option<Nat>) -> Result<bool, String> {
+  let subscription_id = &subscription.subscription_id;
+  let (Some(mut cursor), Some(service)) = (at_block, chain_service(subscription.chain_id)) else {
+    return Ok(true);
+  };
+
+  loop {
+    if !is_excluded_at(subscription_id, &cursor) {
+      return Ok(false);
+    }
+
+    let current = service.last_processed_block.borrow().clone();
+    if current <= cursor {
+      return Ok(true);
+    }
+
+    let to_block = backfill_chunk_end(&cursor, &current);
+    let logs = fetch_logs_range(&service.config, cursor.clone() + 1u32, to_block.clone(), subscription).await?;
+
+    if !is_excluded_at(subscription_id, &cursor) {
+      return Ok(false);
+    }
+    set_status(
+      subscription_id,
+      SubscriptionStatus::Paused {
+        mode: PauseMode::Exclude,
+        at_block: Some(to_block.clone()),
+      },
+    );
+
+    let active = SubscriptionInfo {
+      status: SubscriptionStatus::Active,
+      ..subscription.clone()
+    };
+    publish_events_to(&active, logs_to_events(subscription.chain_id, logs)).await;
+    cursor = to_block;
+  }
+}
+
+/// Last block of the next backfill chunk after `cursor`. Every block is assumed to hold up to
+/// `estimate_events_num` events of the address, as a monitoring interval does.
+fn backfill_chunk_end(cursor: &Nat, current: &Nat) -> Nat {
+  let missed_blocks = u32::try_from((current.clone() - cursor.clone()).0).unwrap_or(u32::MAX);
+  let chunk_blocks = calculate_request_chunk_size(get_state_value!(estimate_events_num), missed_blocks);
+
+  current.clone().min(cursor.clone() + chunk_blocks)
+}
+
+/// Whether the subscription is still paused in `PauseMode::Exclude` at the block.
+fn is_excluded_at(subscription_id: &Nat, block: &Nat) -> bool {
+  crate::STATE.with(|state| {
+    state.borrow().subscriptions.get(subscription_id).is_some_and(|sub| {
+      sub.status
+        == SubscriptionStatus::Paused {
+          mode: PauseMode::Exclude,
+          at_block: Some(block.clone()),
+        }
+    })
+  })
+}
+
+/// Replaces the delegated managers. Only the owner can do it, managers can't delegate further.
+pub fn set_managers(caller: Principal, subscription_id: Nat, managers: Vec<Principal>) -> SubscriptionResult {
+  crate::STATE.with(|state| {
+    let mut state = state.borrow_mut();
+    match state.subscriptions.get_mut(&subscription_id) {
+      Some(subscription) if subscription.owner == caller => {
+        subscription.managers = managers;
+        SubscriptionResult::Ok
+      }
+      Some(_) => SubscriptionResult::Err(SubscriptionError::Unauthorized),
+      None => SubscriptionResult::Err(SubscriptionError::NotFound(subscription_id)),
     }
+  })
+}
+
+/// Stops fetching for and charging a subscription whose subscriber keeps failing.
+/// Returns `false` if it's not active (anymore).
+pub fn suspend_subscription(subscription_id: &Nat, consecutive_failures: u32) -> bool {
+  let subscription = crate::STATE.with(|state| state.borrow().subscriptions.get(subscription_id).cloned());
+  let Some(subscription) = subscription.filter(SubscriptionInfo::is_active) else {
+    return false;
+  };
+
+  FILTERS_MANAGER.with(|manager| {
+    manager
+      .borrow_mut()
+      .remove_filter(subscription.chain_id, &subscription.filter);
+  });
+  // nothing buffered is delivered to a failing subscriber
+  drop_buffer(subscription_id);
+  set_status(subscription_id, SubscriptionStatus::Suspended { consecutive_failures });
+  sync_chain_monitoring(subscription.chain_id);
+
+  true
+}
+
+/// Brings a suspended subscription back, delivery continues with newly fetched logs.
+pub fn reactivate_subscription(caller: Principal, subscription_id: Nat) -> SubscriptionResult {
+  let subscription = match authorize(&caller, &subscription_id) {
+    Ok(subscription) => subscription,
+    Err(err) => return SubscriptionResult::Err(err),
+  };
+
+  if !matches!(subscription.status, SubscriptionStatus::Suspended { .. }) {
+    return SubscriptionResult::Err(SubscriptionError::NotSuspended);
+  }
+
+  FILTERS_MANAGER.with(|manager| {
+    manager
+      .borrow_mut()
+      .add_filter(subscription.chain_id, &subscription.filter);
+  });
+  reset_failures(&subscription_id);
+  set_status(&subscription_id, SubscriptionStatus::Active);
+  sync_chain_monitoring(subscription.chain_id);
+
+  SubscriptionResult::Ok
+}
+
+/// Suspends an active subscription whose subscriber ran out of balance.
+/// Returns the deadline for a top-up, `None` if it's not active (anymore).
+/// The subscription is cancelled at the deadline even if the chain timer was stopped meanwhile.
+pub fn start_grace_period(subscription_id: &Nat) -> Option<u64> {
+  let subscription = crate::STATE.with(|state| state.borrow().subscriptions.get(subscription_id).cloned());
+  let subscription = subscription.filter(SubscriptionInfo::is_active)?;
+
+  FILTERS_MANAGER.with(|manager| {
+    manager
+      .borrow_mut()
+      .remove_filter(subscription.chain_id, &subscription.filter);
+  });
+
+  let deadline = timestamp_nanos().saturating_add(LOW_BALANCE_GRACE_PERIOD_SECS * 1_000_000_000);
+  set_status(subscription_id, SubscriptionStatus::LowBalance { deadline });
+  sync_chain_monitoring(subscription.chain_id);
+
+  let chain_id = subscription.chain_id;
+  ic_cdk_timers::set_timer(Duration::from_secs(LOW_BALANCE_GRACE_PERIOD_SECS), move || {
+    ic_cdk::spawn(expire_subscriptions(chain_id, chain_cursor(chain_id)));
+  });
+
+  Some(deadline)
+}
+
+/// Brings back subscriptions of the subscriber suspended for low balance once it can pay again.
+/// Ones past their deadline are left for the expiry sweep to cancel.
+pub fn restore_funded_subscriptions(subscriber: Principal) {
+  let balance = Balances::get_balance(&subscriber).unwrap();
+  let now = timestamp_nanos();
+  let restored: Vec<SubscriptionInfo> = crate::STATE.with(|state| {
+    state
+      .borrow()
+      .subscriptions
+      .values()
+      .filter(|sub| sub.subscriber_principal == subscriber)
+      .filter(|sub| matches!(sub.status, SubscriptionStatus::LowBalance { deadline } if now < deadline))
+      .filter(|sub| balance >= notification_cost_estimate(sub.chain_id))
+      .cloned()
+      .collect()
+  });
 
-    UnsubscribeResult::Ok()
-  } else {
-    UnsubscribeResult::Err(format!("Subscription with ID {} not found", subscription_id))
+  for subscription in restored {
+    FILTERS_MANAGER.with(|manager| {
+      manager
+        .borrow_mut()
+        .add_filter(subscription.chain_id, &subscription.filter);
+    });
+    set_status(&subscription.subscription_id, SubscriptionStatus::Active);
+    sync_chain_monitoring(subscription.chain_id);
+
+    log_with_metrics!(
+      "Subscription restored after top-up: ID={}",
+      subscription.subscription_id
+    );
   }
 }
 
@@ -172,8 +664,8 @@ pub fn unsubscribe(caller: Principal, subscription_id: Nat) -> UnsubscribeResult
 mod tests {
   use std::str::FromStr;
 
-  use evm_logs_types::{Filter, RegisterSubscriptionResult, SubscriptionRegistration};
-  use evm_rpc_types::{Hex20, Hex32};
+  use evm_logs_types::Filter;
+  use evm_rpc_types::Hex20;
 
   use super::*;
   #[test]
@@ -188,4 +680,186 @@ mod tests {
         assert!(matches!(result, UnsubscribeResult::Err(_)));
       })
   }
+
+  const ADDR1_HEX20: &str = "0xd42AcA6E135D1dae6317e776F7EB96Eb91b8eb91";
+  const ADDR2_HEX20: &str = "0xDA2efffa45cf5D960209aA0921Cf42a4a2a085cf";
+
+  fn create_filter(address: &str) -> Filter {
+    Filter {
+      address: Hex20::from_str(address).unwrap(),
+      topics: None,
+      predicate: None,
+      data_masks: None,
+    }
+  }
+
+  fn insert_subscription(id: u32, owner: Principal, managers: Vec<Principal>) {
+    let subscription = SubscriptionInfo {
+      subscription_id: Nat::from(id),
+      subscriber_principal: owner,
+      owner,
+      managers,
+      chain_id: 1,
+      filter: create_filter(ADDR1_HEX20),
+      stats: vec![],
+      delivery: Default::default(),
+      status: Default::default(),
+      expiry: None,
+      remaining_events: None,
+      memo: None,
+      labels: vec![],
+      callback: None,
+      failure_threshold: DEFAULT_FAILURE_THRESHOLD,
+    };
+    FILTERS_MANAGER.with(|manager| manager.borrow_mut().add_filter(1, &subscription.filter));
+    crate::STATE.with(|state| {
+      state.borrow_mut().subscriptions.insert(Nat::from(id), subscription);
+    });
+  }
+
+  #[test]
+  fn test_authorize_owner_and_managers_only() {
+    let owner = Principal::from_slice(&[1]);
+    let manager = Principal::from_slice(&[2]);
+    let stranger = Principal::from_slice(&[3]);
+    insert_subscription(1, owner, vec![manager]);
+
+    assert!(authorize(&owner, &Nat::from(1u32)).is_ok());
+    assert!(authorize(&manager, &Nat::from(1u32)).is_ok());
+    assert_eq!(
+      authorize(&stranger, &Nat::from(1u32)).unwrap_err(),
+      SubscriptionError::Unauthorized
+    );
+    assert_eq!(
+      authorize(&owner, &Nat::from(2u32)).unwrap_err(),
+      SubscriptionError::NotFound(Nat::from(2u32))
+    );
+  }
+
+  #[test]
+  fn test_unsubscribe_by_stranger_is_rejected() {
+    let owner = Principal::from_slice(&[1]);
+    insert_subscription(1, owner, vec![]);
+
+    let result = unsubscribe(Principal::from_slice(&[3]), Nat::from(1u32));
+    assert!(matches!(
+      result,
+      UnsubscribeResult::Err(SubscriptionError::Unauthorized)
+    ));
+    assert!(crate::STATE.with(|state| state.borrow().subscriptions.contains_key(&Nat::from(1u32))));
+  }
+
+  #[test]
+  fn test_only_owner_sets_managers() {
+    let owner = Principal::from_slice(&[1]);
+    let manager = Principal::from_slice(&[2]);
+    insert_subscription(1, owner, vec![manager]);
+
+    assert!(matches!(
+      set_managers(manager, Nat::from(1u32), vec![]),
+      SubscriptionResult::Err(SubscriptionError::Unauthorized)
+    ));
+    assert!(matches!(
+      set_managers(owner, Nat::from(1u32), vec![]),
+      SubscriptionResult::Ok
+    ));
+  }
+
+  #[test]
+  fn test_only_subscriber_and_its_delegates_may_charge_it() {
+    let subscriber = Principal::from_slice(&[1]);
+    let manager = Principal::from_slice(&[2]);
+    let stranger = Principal::from_slice(&[3]);
+
+    assert!(may_charge(&subscriber, &subscriber));
+    assert!(!may_charge(&manager, &subscriber));
+
+    insert_subscription(1, subscriber, vec![manager]);
+
+    assert!(may_charge(&manager, &subscriber));
+    assert!(!may_charge(&stranger, &subscriber));
+    assert!(!may_charge(&subscriber, &manager));
+  }
+
+  #[test]
+  fn test_replace_filter_keeps_id_and_swaps_filters() {
+    let owner = Principal::from_slice(&[1]);
+    insert_subscription(1, owner, vec![]);
+
+    let subscription = authorize(&owner, &Nat::from(1u32)).unwrap();
+    replace_filter(&subscription, &create_filter(ADDR2_HEX20));
+
+    let filter = crate::STATE.with(|state| state.borrow().subscriptions[&Nat::from(1u32)].filter.clone());
+    assert_eq!(filter, create_filter(ADDR2_HEX20));
+
+    let (addresses, _) = FILTERS_MANAGER.with(|manager| manager.borrow().get_active_addresses_and_topics(1));
+    assert_eq!(addresses, vec![Hex20::from_str(ADDR2_HEX20).unwrap()]);
+  }
+
+  #[test]
+  fn test_update_filter_requires_authorization() {
+    insert_subscription(1, Principal::from_slice(&[1]), vec![]);
+
+    let result = update_filter(Principal::from_slice(&[3]), Nat::from(1u32), create_filter(ADDR2_HEX20));
+    assert!(matches!(
+      result,
+      SubscriptionResult::Err(SubscriptionError::Unauthorized)
+    ));
+  }
+
+  #[test]
+  fn test_backfill_chunks_fit_into_a_response() {
+    let chunk_blocks = calculate_request_chunk_size(get_state_value!(estimate_events_num), u32::MAX);
+
+    assert_eq!(
+      backfill_chunk_end(&Nat::from(100u32), &Nat::from(101u32)),
+      Nat::from(101u32)
+    );
+    assert_eq!(
+      backfill_chunk_end(&Nat::from(100u32), &Nat::from(1_000_000_000u64)),
+      Nat::from(100 + chunk_blocks)
+    );
+  }
+
+  #[test]
+  fn test_excluded_at_tracks_backfill_cursor() {
+    insert_subscription(1, Principal::from_slice(&[1]), vec![]);
+    let excluded_at = |block: u32| SubscriptionStatus::Paused {
+      mode: PauseMode::Exclude,
+      at_block: Some(Nat::from(block)),
+    };
+
+    assert!(!is_excluded_at(&Nat::from(1u32), &Nat::from(10u32)));
+
+    set_status(&Nat::from(1u32), excluded_at(10));
+    assert!(is_excluded_at(&Nat::from(1u32), &Nat::from(10u32)));
+    assert!(!is_excluded_at(&Nat::from(1u32), &Nat::from(20u32)));
+    assert!(!is_excluded_at(&Nat::from(2u32), &Nat::from(10u32)));
+  }
+
+  #[test]
+  fn test_suspend_and_reactivate() {
+    let owner = Principal::from_slice(&[1]);
+    insert_subscription(1, owner, vec![]);
+    let active_addresses = || FILTERS_MANAGER.with(|manager| manager.borrow().get_active_addresses_and_topics(1).0);
+
+    assert!(has_fetching_subscriptions(1));
+    assert!(suspend_subscription(&Nat::from(1u32), 10));
+    assert!(!suspend_subscription(&Nat::from(1u32), 11));
+    assert!(active_addresses().is_empty());
+    assert!(!has_fetching_subscriptions(1));
+
+    assert!(matches!(
+      reactivate_subscription(owner, Nat::from(1u32)),
+      SubscriptionResult::Ok
+    ));
+    assert!(authorize(&owner, &Nat::from(1u32)).unwrap().is_active());
+    assert_eq!(active_addresses(), vec![Hex20::from_str(ADDR1_HEX20).unwrap()]);
+    assert!(has_fetching_subscriptions(1));
+
+    assert!(matches!(
+      reactivate_subscription(owner, Nat::from(1u32)),
+      SubscriptionResult::Err(SubscriptionError::NotSuspended)
+    ));
+  }
 }
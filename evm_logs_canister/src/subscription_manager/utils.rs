@@ -1,4 +1,14 @@
-use evm_logs_types::{Event, Filter};
+use candid::Principal;
+use evm_logs_types::{CallbackTarget, DeliveryMode, Event, Filter, Label};
+
+use super::coalescing::validate_coalesce_config;
+use crate::{
+  constants::{MAX_CALLBACK_METHOD_SIZE, MAX_LABEL_SIZE, MAX_LABELS, MAX_MEMO_SIZE},
+  log_filters::{
+    data_mask::{data_matches_masks, validate_data_masks},
+    predicate::{event_matches_predicate, validate_predicate},
+  },
+};
 
 // Function to check if particular event matches specific filter
 pub fn event_matches_filter(event: &Event, subscribers_filter: &Filter) -> bool {
@@ -13,22 +23,101 @@ pub fn event_matches_filter(event: &Event, subscribers_filter: &Filter) -> bool
       return false;
     }
 
-    return filter_topics.iter().enumerate().all(|(i, filter_topic_set)| {
+    let topics_match = filter_topics.iter().enumerate().all(|(i, filter_topic_set)| {
       event_topics
         .get(i)
         .is_some_and(|event_topic| filter_topic_set.contains(event_topic))
     });
+
+    if !topics_match {
+      return false;
+    }
+  }
+
+  let data_masks_match = subscribers_filter
+    .data_masks
+    .as_ref()
+    .is_none_or(|data_masks| data_matches_masks(event.log_entry.data.as_ref(), data_masks));
+
+  if !data_masks_match {
+    return false;
+  }
+
+  if let Some(predicate) = &subscribers_filter.predicate {
+    return event_matches_predicate(&event.log_entry, predicate);
   }
 
   true
 }
 
+// Function to check that the filter can be registered, returns a human readable reason otherwise
+pub fn validate_filter(filter: &Filter) -> Result<(), String> {
+  if let Some(data_masks) = &filter.data_masks {
+    validate_data_masks(data_masks)?;
+  }
+
+  if let Some(predicate) = &filter.predicate {
+    validate_predicate(predicate)?;
+  }
+
+  Ok(())
+}
+
+pub fn validate_delivery_mode(delivery: &DeliveryMode) -> Result<(), String> {
+  match delivery {
+    DeliveryMode::Immediate | DeliveryMode::ByTransaction => Ok(()),
+    DeliveryMode::Coalesce(config) => validate_coalesce_config(config),
+  }
+}
+
+/// Memo and labels are sent with every notification, so they are kept small
+pub fn validate_metadata(memo: Option<&Vec<u8>>, labels: &[Label]) -> Result<(), String> {
+  if memo.is_some_and(|memo| memo.len() > MAX_MEMO_SIZE) {
+    return Err(format!("Memo can't be longer than {} bytes", MAX_MEMO_SIZE));
+  }
+
+  if labels.len() > MAX_LABELS {
+    return Err(format!("There can't be more than {} labels", MAX_LABELS));
+  }
+
+  if labels
+    .iter()
+    .any(|label| label.key.len() > MAX_LABEL_SIZE || label.value.len() > MAX_LABEL_SIZE)
+  {
+    return Err(format!(
+      "Label keys and values can't be longer than {} bytes",
+      MAX_LABEL_SIZE
+    ));
+  }
+
+  Ok(())
+}
+
+/// The proxy calls the callback on behalf of the evm_logs canister, so it can only target canisters that agreed to
+/// receive the notifications: the subscriber and the owner of the subscription.
+pub fn validate_callback(callback: &CallbackTarget, subscriber: &Principal, owner: &Principal) -> Result<(), String> {
+  if callback.canister != *subscriber && callback.canister != *owner {
+    return Err("Callback canister must be the subscriber or the owner of the subscription".to_string());
+  }
+
+  if callback.method.is_empty() || callback.method.len() > MAX_CALLBACK_METHOD_SIZE {
+    return Err(format!(
+      "Callback method name must be 1 to {} bytes long",
+      MAX_CALLBACK_METHOD_SIZE
+    ));
+  }
+
+  Ok(())
+}
+
 #[cfg(test)]
 mod tests {
   use std::str::FromStr;
 
   use candid::Nat;
-  use evm_logs_types::TopicsPosition;
+  use evm_logs_types::{
+    AbiParamKind, CompareOp, DataMask, EventParam, EventPredicate, Predicate, PredicateValue, TopicsPosition,
+  };
   use evm_rpc_types::{Hex, Hex20, Hex32, LogEntry};
 
   use super::*;
@@ -73,6 +162,8 @@ mod tests {
           })
           .collect()
       }),
+      predicate: None,
+      data_masks: None,
     }
   }
 
@@ -156,4 +247,80 @@ mod tests {
 
     assert!(!event_matches_filter(&event, &filter));
   }
+
+  #[test]
+  fn test_event_matches_filter_predicate() {
+    let mut event = create_event(ADDR1_HEX20, Some(vec![TOPIC1_HEX32]));
+    event.log_entry.data = Hex::from([vec![0u8; 31], vec![42u8]].concat());
+
+    let mut filter = create_filter(ADDR1_HEX20_LOWER, Some(vec![vec![TOPIC1_HEX32]]));
+    let predicate_for = |threshold: u32| EventPredicate {
+      params: vec![EventParam {
+        name: "value".to_string(),
+        kind: AbiParamKind::Uint,
+        indexed: false,
+      }],
+      condition: Predicate::Compare {
+        field: "value".to_string(),
+        op: CompareOp::Gt,
+        value: PredicateValue::Nat(Nat::from(threshold)),
+      },
+    };
+
+    filter.predicate = Some(predicate_for(41));
+    assert!(event_matches_filter(&event, &filter));
+
+    filter.predicate = Some(predicate_for(42));
+    assert!(!event_matches_filter(&event, &filter));
+  }
+
+  #[test]
+  fn test_event_matches_filter_data_masks() {
+    let mut event = create_event(ADDR1_HEX20, Some(vec![TOPIC1_HEX32]));
+    event.log_entry.data = Hex::from([vec![0u8; 31], vec![42u8]].concat());
+
+    let mut filter = create_filter(ADDR1_HEX20_LOWER, Some(vec![vec![TOPIC1_HEX32]]));
+    let last_byte_is = |value: u8| DataMask {
+      offset: 31,
+      mask: vec![0xff],
+      value: vec![value],
+      negate: false,
+    };
+
+    filter.data_masks = Some(vec![last_byte_is(42)]);
+    assert!(event_matches_filter(&event, &filter));
+
+    filter.data_masks = Some(vec![last_byte_is(42), last_byte_is(43)]);
+    assert!(!event_matches_filter(&event, &filter));
+  }
+
+  #[test]
+  fn test_validate_metadata() {
+    let label = |key: &str| Label {
+      key: key.to_string(),
+      value: "router".to_string(),
+    };
+
+    assert!(validate_metadata(Some(&vec![1; MAX_MEMO_SIZE]), &[label("module")]).is_ok());
+    assert!(validate_metadata(Some(&vec![1; MAX_MEMO_SIZE + 1]), &[]).is_err());
+    assert!(validate_metadata(None, &vec![label("module"); MAX_LABELS + 1]).is_err());
+    assert!(validate_metadata(None, &[label(&"k".repeat(MAX_LABEL_SIZE + 1))]).is_err());
+  }
+
+  #[test]
+  fn test_validate_callback() {
+    let subscriber = Principal::from_slice(&[1]);
+    let owner = Principal::from_slice(&[2]);
+    let callback = |canister: Principal, method: &str| CallbackTarget {
+      canister,
+      method: method.to_string(),
+    };
+    let validate = |callback: CallbackTarget| validate_callback(&callback, &subscriber, &owner);
+
+    assert!(validate(callback(subscriber, "handle_swap")).is_ok());
+    assert!(validate(callback(owner, "handle_swap")).is_ok());
+    assert!(validate(callback(Principal::from_slice(&[3]), "handle_swap")).is_err());
+    assert!(validate(callback(subscriber, "")).is_err());
+    assert!(validate(callback(subscriber, &"m".repeat(MAX_CALLBACK_METHOD_SIZE + 1))).is_err());
+  }
 }
@@ -0,0 +1,119 @@
+use candid::{Nat, Principal};
+use evm_logs_types::{BalanceChangeKind, WithdrawBalanceError, WithdrawBalanceResult};
+use ic_cdk::api::management_canister::main::{CanisterIdRecord, deposit_cycles};
+
+use crate::{
+  STATE, log_with_metrics,
+  types::{
+    balance_ledger::{ChargeReference, record_balance_change},
+    balances::Balances,
+  },
+};
+
+/// Deposits `amount` cycles of the `account` balance into `destination` through the management canister.
+pub async fn withdraw(account: Principal, amount: Nat, destination: Principal) -> WithdrawBalanceResult {
+  // cycles are taken off the balance before the call, so deliveries running meanwhile can't spend them too
+  let cycles = match reserve(&account, &amount) {
+    Ok(cycles) => cycles,
+    Err(err) => return WithdrawBalanceResult::Err(err),
+  };
+
+  match deposit_cycles(
+    CanisterIdRecord {
+      canister_id: destination,
+    },
+    cycles,
+  )
+  .await
+  {
+    Ok(()) => {
+      record_balance_change(
+        account,
+        BalanceChangeKind::Refund { destination },
+        amount.clone(),
+        ChargeReference::default(),
+      );
+      log_with_metrics!(
+        "Withdrawn {} cycles of {} to {}",
+        cycles,
+        account.to_text(),
+        destination.to_text()
+      );
+      WithdrawBalanceResult::Ok(amount)
+    }
+    Err((code, message)) => {
+      // the cycles stay with this canister when the deposit is rejected, give them back to the balance
+      Balances::top_up(account, amount).unwrap();
+      log_with_metrics!(
+        "Failed to withdraw cycles of {} to {}: {:?} {}",
+        account.to_text(),
+        destination.to_text(),
+        code,
+        message
+      );
+      WithdrawBalanceResult::Err(WithdrawBalanceError::DepositFailed(format!("{:?}: {}", code, message)))
+    }
+  }
+}
+
+/// Hands the whole remaining balance back to the subscriber once it has no subscriptions left.
+pub async fn refund_if_unsubscribed(subscriber: Principal) {
+  let has_subscriptions = STATE.with(|state| state.borrow().subscribers.contains_key(&subscriber));
+  let balance = Balances::get_balance(&subscriber).unwrap();
+
+  if has_subscriptions || balance == 0u32 {
+    return;
+  }
+
+  if let WithdrawBalanceResult::Err(err) = withdraw(subscriber, balance, subscriber).await {
+    log_with_metrics!("Refund to {} failed, balance kept: {:?}", subscriber.to_text(), err);
+  }
+}
+
+/// Takes `amount` off the balance, returns it as the cycles to attach.
+fn reserve(account: &Principal, amount: &Nat) -> Result<u128, WithdrawBalanceError> {
+  let balance = Balances::get_balance(account).unwrap();
+  let insufficient = || WithdrawBalanceError::InsufficientBalance {
+    balance: balance.clone(),
+  };
+
+  let cycles = u128::try_from(amount.0.clone()).map_err(|_| insufficient())?;
+  Balances::reduce(account, amount.clone()).map_err(|_| insufficient())?;
+
+  Ok(cycles)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_reserve_reduces_balance() {
+    let account = Principal::from_slice(&[1]);
+    Balances::top_up(account, Nat::from(1_000u32)).unwrap();
+
+    assert_eq!(reserve(&account, &Nat::from(400u32)).unwrap(), 400);
+    assert_eq!(Balances::get_balance(&account).unwrap(), Nat::from(600u32));
+  }
+
+  #[test]
+  fn test_reserve_rejects_more_than_balance() {
+    let account = Principal::from_slice(&[2]);
+    Balances::top_up(account, Nat::from(100u32)).unwrap();
+
+    let result = reserve(&account, &Nat::from(101u32));
+
+    assert!(matches!(
+      result,
+      Err(WithdrawBalanceError::InsufficientBalance { balance }) if balance == 100u32
+    ));
+    assert_eq!(Balances::get_balance(&account).unwrap(), Nat::from(100u32));
+  }
+
+  #[test]
+  fn test_reserve_without_balance() {
+    let result = reserve(&Principal::from_slice(&[3]), &Nat::from(1u32));
+
+    assert!(matches!(result, Err(WithdrawBalanceError::InsufficientBalance { .. })));
+  }
+}
@@ -1,3 +1,10 @@
+pub mod balance_ledger;
 pub mod balances;
 pub mod config;
+pub mod event_rates;
+pub mod icrc;
+pub mod pricing;
+pub mod roles;
+pub mod seen_logs;
 pub mod state;
+pub mod state_v0;
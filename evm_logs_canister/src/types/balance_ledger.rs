@@ -0,0 +1,169 @@
+use std::collections::{HashMap, VecDeque};
+
+use candid::{CandidType, Nat, Principal};
+use evm_logs_types::{BalanceChangeKind, BalanceHistoryPage, BalanceLedgerEntry};
+use serde::{Deserialize, Serialize};
+
+use super::balances::Balances;
+use crate::{
+  STATE,
+  constants::{DEFAULT_BALANCE_HISTORY_PAGE_SIZE, MAX_BALANCE_HISTORY_ENTRIES},
+  internals::misc::timestamp_nanos,
+};
+
+/// Append-only history of balance changes, the last `capacity` entries per principal.
+#[derive(Clone, CandidType, Serialize, Deserialize, Debug, Default)]
+pub struct BalanceLedger {
+  entries: HashMap<Principal, VecDeque<BalanceLedgerEntry>>,
+  next_id: u64,
+}
+
+/// What a balance change was made for
+#[derive(Clone, Debug, Default)]
+pub struct ChargeReference {
+  pub subscription_id: Option<Nat>,
+  pub chain_id: Option<u32>,
+  pub from_block: Option<Nat>,
+  pub to_block: Option<Nat>,
+}
+
+impl BalanceLedger {
+  /// Assigns the entry its id, returns the id.
+  pub fn append(&mut self, principal: Principal, mut entry: BalanceLedgerEntry, capacity: usize) -> u64 {
+    entry.id = self.next_id;
+    self.next_id += 1;
+
+    let entries = self.entries.entry(principal).or_default();
+    entries.push_back(entry);
+    while entries.len() > capacity {
+      entries.pop_front();
+    }
+
+    self.next_id - 1
+  }
+
+  /// Entries of the principal after `from_id`, `limit` at most.
+  pub fn page(&self, principal: &Principal, from_id: Option<u64>, limit: usize) -> BalanceHistoryPage {
+    let Some(entries) = self.entries.get(principal) else {
+      return BalanceHistoryPage {
+        entries: vec![],
+        next_from_id: None,
+      };
+    };
+
+    // ids are increasing, skip up to the cursor
+    let start = from_id.map_or(0, |from_id| entries.partition_point(|entry| entry.id <= from_id));
+    let mut page: Vec<BalanceLedgerEntry> = entries.range(start..).take(limit + 1).cloned().collect();
+
+    let next_from_id = if page.len() > limit {
+      page.truncate(limit);
+      page.last().map(|entry| entry.id)
+    } else {
+      None
+    };
+
+    BalanceHistoryPage {
+      entries: page,
+      next_from_id,
+    }
+  }
+}
+
+/// Records a change already applied to the balance of `principal`.
+pub fn record_balance_change(principal: Principal, kind: BalanceChangeKind, amount: Nat, reference: ChargeReference) {
+  let entry = BalanceLedgerEntry {
+    id: 0,
+    timestamp: timestamp_nanos(),
+    kind,
+    amount,
+    balance_after: Balances::get_balance(&principal).unwrap(),
+    subscription_id: reference.subscription_id,
+    chain_id: reference.chain_id,
+    from_block: reference.from_block,
+    to_block: reference.to_block,
+  };
+
+  STATE.with(|state| {
+    state
+      .borrow_mut()
+      .balance_ledger
+      .append(principal, entry, MAX_BALANCE_HISTORY_ENTRIES)
+  });
+}
+
+pub fn get_balance_history(principal: &Principal, from_id: Option<u64>, limit: Option<u32>) -> BalanceHistoryPage {
+  let limit = limit
+    .unwrap_or(DEFAULT_BALANCE_HISTORY_PAGE_SIZE)
+    .clamp(1, MAX_BALANCE_HISTORY_ENTRIES as u32) as usize;
+
+  STATE.with(|state| state.borrow().balance_ledger.page(principal, from_id, limit))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn create_entry(amount: u32) -> BalanceLedgerEntry {
+    BalanceLedgerEntry {
+      id: 0,
+      timestamp: 0,
+      kind: BalanceChangeKind::TopUp,
+      amount: Nat::from(amount),
+      balance_after: Nat::from(amount),
+      subscription_id: None,
+      chain_id: None,
+      from_block: None,
+      to_block: None,
+    }
+  }
+
+  fn amounts(page: &BalanceHistoryPage) -> Vec<Nat> {
+    page.entries.iter().map(|entry| entry.amount.clone()).collect()
+  }
+
+  #[test]
+  fn test_append_keeps_last_entries() {
+    let mut ledger = BalanceLedger::default();
+    let principal = Principal::from_slice(&[1]);
+
+    for amount in 0..5 {
+      ledger.append(principal, create_entry(amount), 3);
+    }
+
+    let page = ledger.page(&principal, None, 10);
+    assert_eq!(amounts(&page), vec![Nat::from(2u32), Nat::from(3u32), Nat::from(4u32)]);
+    assert_eq!(page.next_from_id, None);
+  }
+
+  #[test]
+  fn test_page_follows_cursor_per_principal() {
+    let mut ledger = BalanceLedger::default();
+    let principal = Principal::from_slice(&[1]);
+    let other = Principal::from_slice(&[2]);
+
+    for amount in 0..5 {
+      ledger.append(principal, create_entry(amount), 10);
+      ledger.append(other, create_entry(100), 10);
+    }
+
+    let first = ledger.page(&principal, None, 2);
+    assert_eq!(amounts(&first), vec![Nat::from(0u32), Nat::from(1u32)]);
+
+    let second = ledger.page(&principal, first.next_from_id, 2);
+    assert_eq!(amounts(&second), vec![Nat::from(2u32), Nat::from(3u32)]);
+
+    let last = ledger.page(&principal, second.next_from_id, 2);
+    assert_eq!(amounts(&last), vec![Nat::from(4u32)]);
+    assert_eq!(last.next_from_id, None);
+  }
+
+  #[test]
+  fn test_page_of_unknown_principal_is_empty() {
+    let ledger = BalanceLedger::default();
+
+    let page = ledger.page(&Principal::from_slice(&[1]), None, 10);
+
+    assert!(page.entries.is_empty());
+    assert_eq!(page.next_from_id, None);
+  }
+}
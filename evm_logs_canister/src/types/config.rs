@@ -1,6 +1,12 @@
 use candid::{CandidType, Principal};
+use evm_logs_types::ConfigUpdate;
 use serde::{Deserialize, Serialize};
 
+use crate::{STATE, constants::EVM_EVENT_SIZE_BYTES};
+
+/// Largest response of an inter-canister call
+const MAX_RESPONSE_BYTES_LIMIT: u32 = 2_000_000;
+
 #[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
 pub struct Config {
   pub evm_rpc_canister: Principal,
@@ -8,3 +14,122 @@ pub struct Config {
   pub estimate_events_num: u32,
   pub max_response_bytes: u32,
 }
+
+impl Config {
+  pub fn current() -> Self {
+    STATE.with(|state| {
+      let state = state.borrow();
+      Self {
+        evm_rpc_canister: state.evm_rpc_canister,
+        proxy_canister: state.proxy_canister,
+        estimate_events_num: state.estimate_events_num,
+        max_response_bytes: state.max_response_bytes,
+      }
+    })
+  }
+
+  /// The config with the update applied, if the result is valid.
+  pub fn updated(&self, update: ConfigUpdate) -> Result<Self, String> {
+    let config = Self {
+      evm_rpc_canister: update.evm_rpc_canister.unwrap_or(self.evm_rpc_canister),
+      proxy_canister: update.proxy_canister.unwrap_or(self.proxy_canister),
+      estimate_events_num: update.estimate_events_num.unwrap_or(self.estimate_events_num),
+      max_response_bytes: update.max_response_bytes.unwrap_or(self.max_response_bytes),
+    };
+    config.validate()?;
+
+    Ok(config)
+  }
+
+  /// Checks applied to the config on install, `update_config` and upgrades.
+  pub fn validate(&self) -> Result<(), String> {
+    if self.evm_rpc_canister == Principal::anonymous() || self.proxy_canister == Principal::anonymous() {
+      return Err("Canister ids can't be anonymous".to_string());
+    }
+
+    if self.estimate_events_num == 0 {
+      return Err("Estimated events number must be positive".to_string());
+    }
+
+    if self.max_response_bytes == 0 || self.max_response_bytes > MAX_RESPONSE_BYTES_LIMIT {
+      return Err(format!(
+        "Max response bytes must be within 1..={}",
+        MAX_RESPONSE_BYTES_LIMIT
+      ));
+    }
+
+    // responses for at least one address have to fit, see `calculate_request_chunk_size`
+    let bytes_per_address = self.estimate_events_num.checked_mul(EVM_EVENT_SIZE_BYTES);
+    if bytes_per_address.is_none_or(|bytes| bytes > self.max_response_bytes) {
+      return Err("Estimated events of one address exceed max response bytes".to_string());
+    }
+
+    Ok(())
+  }
+
+  /// Size `eth_getLogs` responses are expected to have, see `generate_chain_configs`
+  pub fn response_size_estimate(&self) -> u64 {
+    (self.estimate_events_num * EVM_EVENT_SIZE_BYTES) as u64
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn create_config() -> Config {
+    Config {
+      evm_rpc_canister: Principal::from_slice(&[1]),
+      proxy_canister: Principal::from_slice(&[2]),
+      estimate_events_num: 30,
+      max_response_bytes: 1_000_000,
+    }
+  }
+
+  #[test]
+  fn test_updated_keeps_fields_left_out() {
+    let update = ConfigUpdate {
+      proxy_canister: Some(Principal::from_slice(&[3])),
+      max_response_bytes: Some(500_000),
+      ..Default::default()
+    };
+
+    let config = create_config().updated(update).unwrap();
+
+    assert_eq!(config.evm_rpc_canister, Principal::from_slice(&[1]));
+    assert_eq!(config.proxy_canister, Principal::from_slice(&[3]));
+    assert_eq!(config.estimate_events_num, 30);
+    assert_eq!(config.max_response_bytes, 500_000);
+  }
+
+  #[test]
+  fn test_updated_rejects_invalid_values() {
+    let invalid_updates = [
+      ConfigUpdate {
+        proxy_canister: Some(Principal::anonymous()),
+        ..Default::default()
+      },
+      ConfigUpdate {
+        estimate_events_num: Some(0),
+        ..Default::default()
+      },
+      ConfigUpdate {
+        max_response_bytes: Some(MAX_RESPONSE_BYTES_LIMIT + 1),
+        ..Default::default()
+      },
+      // 30 events of 800 bytes don't fit
+      ConfigUpdate {
+        max_response_bytes: Some(20_000),
+        ..Default::default()
+      },
+      ConfigUpdate {
+        estimate_events_num: Some(u32::MAX),
+        ..Default::default()
+      },
+    ];
+
+    for update in invalid_updates {
+      assert!(create_config().updated(update.clone()).is_err(), "{:?}", update);
+    }
+  }
+}
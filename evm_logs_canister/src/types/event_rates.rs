@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+
+use candid::CandidType;
+use evm_logs_types::Filter;
+use evm_rpc_types::{Hex20, LogEntry};
+use serde::{Deserialize, Serialize};
+
+/// Polls of an address and the events seen for it, by topic0 (`None` for anonymous events).
+#[derive(Clone, Debug, Default, CandidType, Serialize, Deserialize)]
+struct AddressRates {
+  polls: u64,
+  events: HashMap<Option<Vec<u8>>, u64>,
+  last_poll: u64, // number of the last poll that included the address
+}
+
+/// Observed event rates and fetch costs, the basis of subscription quotes.
+#[derive(Clone, Debug, Default, CandidType, Serialize, Deserialize)]
+pub struct EventRates {
+  addresses: HashMap<(u32, Vec<u8>), AddressRates>,
+  fetch_costs: HashMap<u32, FetchCost>,
+  polls: u64,
+}
+
+/// Cycles spent on fetches of a chain and the sum of query shares they were split by.
+#[derive(Clone, Debug, Default, CandidType, Serialize, Deserialize)]
+struct FetchCost {
+  cycles: u128,
+  shares: u128,
+}
+
+impl EventRates {
+  /// Counts one poll of every address and the logs it returned, keeping the rates of `capacity` addresses at most.
+  pub fn record_poll(&mut self, chain_id: u32, addresses: &[Hex20], logs: &[LogEntry], capacity: usize) {
+    self.polls += 1;
+    for address in addresses {
+      let rates = self.addresses.entry((chain_id, address.as_ref().to_vec())).or_default();
+      rates.polls += 1;
+      rates.last_poll = self.polls;
+    }
+
+    for log in logs {
+      if let Some(rates) = self.addresses.get_mut(&(chain_id, log.address.as_ref().to_vec())) {
+        let topic0 = log.topics.first().map(|topic| topic.as_ref().to_vec());
+        *rates.events.entry(topic0).or_default() += 1;
+      }
+    }
+
+    self.drop_least_recently_polled(capacity);
+  }
+
+  /// Addresses stop being polled once their subscriptions are gone, their rates are dropped first.
+  fn drop_least_recently_polled(&mut self, capacity: usize) {
+    if self.addresses.len() <= capacity {
+      return;
+    }
+
+    let mut keys: Vec<(u64, (u32, Vec<u8>))> = self
+      .addresses
+      .iter()
+      .map(|(key, rates)| (rates.last_poll, key.clone()))
+      .collect();
+    keys.sort_unstable();
+
+    for (_, key) in keys.into_iter().take(self.addresses.len() - capacity) {
+      self.addresses.remove(&key);
+    }
+  }
+
+  pub fn record_fetch_cost(&mut self, chain_id: u32, cycles: u128, shares: u64) {
+    let cost = self.fetch_costs.entry(chain_id).or_default();
+    cost.cycles = cost.cycles.saturating_add(cycles);
+    cost.shares = cost.shares.saturating_add(shares as u128);
+  }
+
+  /// Events matching the address and topic0 of the filter and the polls they were seen in.
+  pub fn observed_events(&self, chain_id: u32, filter: &Filter) -> (u64, u64) {
+    let Some(rates) = self.addresses.get(&(chain_id, filter.address.as_ref().to_vec())) else {
+      return (0, 0);
+    };
+
+    let topic0: Option<Vec<Vec<u8>>> = filter
+      .topics
+      .as_ref()
+      .and_then(|topics| topics.first())
+      .filter(|position| !position.is_empty())
+      .map(|position| position.iter().map(|topic| topic.as_ref().to_vec()).collect());
+
+    let events = rates
+      .events
+      .iter()
+      .filter(|(event_topic0, _)| match (&topic0, event_topic0) {
+        (None, _) => true,
+        (Some(topic0), Some(event_topic0)) => topic0.contains(event_topic0),
+        (Some(_), None) => false,
+      })
+      .map(|(_, count)| count)
+      .sum();
+
+    (events, rates.polls)
+  }
+
+  /// Average fetch cycles per query share of the chain, `None` before its first charged fetch.
+  pub fn fetch_cost_per_share(&self, chain_id: u32) -> Option<u64> {
+    let cost = self.fetch_costs.get(&chain_id).filter(|cost| cost.shares > 0)?;
+    Some(u64::try_from(cost.cycles / cost.shares).unwrap_or(u64::MAX))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::str::FromStr;
+
+  use evm_rpc_types::{Hex, Hex32};
+
+  use super::*;
+
+  const ADDRESS: &str = "0xd42AcA6E135D1dae6317e776F7EB96Eb91b8eb91";
+  const TRANSFER: &str = "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef";
+  const APPROVAL: &str = "0x8c5be1e5ebec7d5bd14f71427d1e84f3dd0314c0f7b2291e5b200ac8c7c3b925";
+
+  fn create_log(topic0: &str) -> LogEntry {
+    LogEntry {
+      address: Hex20::from_str(ADDRESS).unwrap(),
+      topics: vec![Hex32::from_str(topic0).unwrap()],
+      data: Hex::from(vec![]),
+      block_number: None,
+      transaction_hash: None,
+      transaction_index: None,
+      block_hash: None,
+      log_index: None,
+      removed: false,
+    }
+  }
+
+  fn create_filter(topic0: Option<&str>) -> Filter {
+    Filter {
+      address: Hex20::from_str(ADDRESS).unwrap(),
+      topics: topic0.map(|topic0| vec![vec![Hex32::from_str(topic0).unwrap()]]),
+      predicate: None,
+      data_masks: None,
+    }
+  }
+
+  #[test]
+  fn test_observed_events_by_topic0() {
+    let mut rates = EventRates::default();
+    let addresses = vec![Hex20::from_str(ADDRESS).unwrap()];
+
+    rates.record_poll(1, &addresses, &[create_log(TRANSFER), create_log(APPROVAL)], 10);
+    rates.record_poll(1, &addresses, &[create_log(TRANSFER)], 10);
+    rates.record_poll(1, &addresses, &[], 10);
+
+    assert_eq!(rates.observed_events(1, &create_filter(None)), (3, 3));
+    assert_eq!(rates.observed_events(1, &create_filter(Some(TRANSFER))), (2, 3));
+    assert_eq!(rates.observed_events(1, &create_filter(Some(APPROVAL))), (1, 3));
+  }
+
+  #[test]
+  fn test_observed_events_of_unpolled_address() {
+    let mut rates = EventRates::default();
+    rates.record_poll(1, &[Hex20::from_str(ADDRESS).unwrap()], &[create_log(TRANSFER)], 10);
+
+    assert_eq!(rates.observed_events(56, &create_filter(None)), (0, 0));
+  }
+
+  #[test]
+  fn test_least_recently_polled_addresses_are_dropped() {
+    let mut rates = EventRates::default();
+    let address = Hex20::from_str(ADDRESS).unwrap();
+    let other = Hex20::from([1; 20]);
+
+    rates.record_poll(1, std::slice::from_ref(&address), &[create_log(TRANSFER)], 2);
+    rates.record_poll(56, std::slice::from_ref(&other), &[], 2);
+    rates.record_poll(1, &[other], &[], 2);
+
+    assert_eq!(rates.addresses.len(), 2);
+    assert_eq!(rates.observed_events(1, &create_filter(None)), (0, 0));
+
+    rates.record_poll(1, &[address], &[create_log(TRANSFER)], 2);
+    assert_eq!(rates.observed_events(1, &create_filter(None)), (1, 1));
+    assert_eq!(rates.addresses.len(), 2);
+  }
+
+  #[test]
+  fn test_fetch_cost_per_share() {
+    let mut rates = EventRates::default();
+    assert_eq!(rates.fetch_cost_per_share(1), None);
+
+    rates.record_fetch_cost(1, 1_000, 4);
+    rates.record_fetch_cost(1, 2_000, 2);
+
+    assert_eq!(rates.fetch_cost_per_share(1), Some(500));
+    assert_eq!(rates.fetch_cost_per_share(56), None);
+  }
+}
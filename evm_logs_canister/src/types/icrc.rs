@@ -0,0 +1,46 @@
+//! The part of the ICRC-1/ICRC-2 ledger interface used for deposits.
+
+use candid::{CandidType, Nat, Principal};
+use serde::{Deserialize, Serialize};
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct Account {
+  pub owner: Principal,
+  pub subaccount: Option<Vec<u8>>,
+}
+
+impl From<Principal> for Account {
+  fn from(owner: Principal) -> Self {
+    Self {
+      owner,
+      subaccount: None,
+    }
+  }
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct TransferFromArgs {
+  pub spender_subaccount: Option<Vec<u8>>,
+  pub from: Account,
+  pub to: Account,
+  pub amount: Nat,
+  pub fee: Option<Nat>,
+  pub memo: Option<Vec<u8>>,
+  pub created_at_time: Option<u64>,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub enum TransferFromError {
+  BadFee { expected_fee: Nat },
+  BadBurn { min_burn_amount: Nat },
+  InsufficientFunds { balance: Nat },
+  InsufficientAllowance { allowance: Nat },
+  TooOld,
+  CreatedInFuture { ledger_time: u64 },
+  Duplicate { duplicate_of: Nat },
+  TemporarilyUnavailable,
+  GenericError { error_code: Nat, message: String },
+}
+
+/// `icrc2_transfer_from` reply, the block index of the transfer on success
+pub type TransferFromResult = Result<Nat, TransferFromError>;
@@ -0,0 +1,149 @@
+use std::collections::HashSet;
+
+use evm_logs_types::{AdminError, AdminResult, PricingSchedule};
+
+use crate::{STATE, get_state_value};
+
+const FULL_PERCENT: u128 = 100;
+
+/// Upper bound of chain multipliers and the margin, guards against typos like `15000` for 150%
+const MAX_PERCENT: u32 = 10_000;
+
+pub fn get_pricing() -> PricingSchedule {
+  get_state_value!(pricing)
+}
+
+pub fn set_pricing(pricing: PricingSchedule) -> AdminResult {
+  if let Err(err) = validate_pricing(&pricing) {
+    return AdminResult::Err(AdminError::ImproperConfig(err));
+  }
+
+  STATE.with(|state| state.borrow_mut().pricing = pricing);
+  AdminResult::Ok
+}
+
+fn validate_pricing(pricing: &PricingSchedule) -> Result<(), String> {
+  if pricing.margin_percent > MAX_PERCENT {
+    return Err(format!("Margin exceeds {}%", MAX_PERCENT));
+  }
+
+  let mut chains = HashSet::new();
+  for multiplier in &pricing.chain_multipliers {
+    if !chains.insert(multiplier.chain_id) {
+      return Err(format!("Duplicate multiplier for chain {}", multiplier.chain_id));
+    }
+    if multiplier.percent == 0 || multiplier.percent > MAX_PERCENT {
+      return Err(format!(
+        "Multiplier of chain {} must be within 1..={}%",
+        multiplier.chain_id, MAX_PERCENT
+      ));
+    }
+  }
+
+  Ok(())
+}
+
+/// Scales `cost` by the chain multiplier and the service margin.
+fn price(pricing: &PricingSchedule, chain_id: u32, cost: u64) -> u64 {
+  let multiplier = pricing
+    .chain_multipliers
+    .iter()
+    .find(|multiplier| multiplier.chain_id == chain_id)
+    .map_or(FULL_PERCENT, |multiplier| multiplier.percent as u128);
+  let margin = FULL_PERCENT + pricing.margin_percent as u128;
+
+  let price = cost as u128 * multiplier * margin / (FULL_PERCENT * FULL_PERCENT);
+  u64::try_from(price).unwrap_or(u64::MAX)
+}
+
+/// Price of an `eth_getLogs` request for `addresses_count` addresses that cost `cycles_used`.
+pub fn fetch_price(pricing: &PricingSchedule, chain_id: u32, cycles_used: u64, addresses_count: u64) -> u64 {
+  let polling_fee = pricing.polling_fee.saturating_mul(addresses_count);
+  price(pricing, chain_id, cycles_used.saturating_add(polling_fee))
+}
+
+/// Price of a notification carrying `events_count` events that cost `cycles_used`.
+pub fn delivery_price(pricing: &PricingSchedule, chain_id: u32, cycles_used: u64, events_count: u64) -> u64 {
+  let delivery_fee = pricing.delivery_fee.saturating_mul(events_count);
+  price(pricing, chain_id, cycles_used.saturating_add(delivery_fee))
+}
+
+#[cfg(test)]
+mod tests {
+  use evm_logs_types::ChainMultiplier;
+
+  use super::*;
+
+  fn create_pricing() -> PricingSchedule {
+    PricingSchedule {
+      delivery_fee: 1_000,
+      polling_fee: 100,
+      chain_multipliers: vec![ChainMultiplier {
+        chain_id: 1,
+        percent: 200,
+      }],
+      margin_percent: 10,
+    }
+  }
+
+  #[test]
+  fn test_default_pricing_charges_cost() {
+    let pricing = PricingSchedule::default();
+
+    assert_eq!(fetch_price(&pricing, 1, 5_000, 3), 5_000);
+    assert_eq!(delivery_price(&pricing, 1, 5_000, 3), 5_000);
+  }
+
+  #[test]
+  fn test_fees_multiplier_and_margin() {
+    let pricing = create_pricing();
+
+    // (5_000 + 3 * 100) * 200% * 110%
+    assert_eq!(fetch_price(&pricing, 1, 5_000, 3), 11_660);
+    // chain without a multiplier: (5_000 + 2 * 1_000) * 110%
+    assert_eq!(delivery_price(&pricing, 56, 5_000, 2), 7_700);
+  }
+
+  #[test]
+  fn test_price_saturates() {
+    let mut pricing = create_pricing();
+    pricing.polling_fee = u64::MAX;
+
+    assert_eq!(fetch_price(&pricing, 1, u64::MAX, 2), u64::MAX);
+  }
+
+  #[test]
+  fn test_validate_pricing() {
+    assert!(validate_pricing(&create_pricing()).is_ok());
+
+    let mut duplicate = create_pricing();
+    duplicate.chain_multipliers.push(ChainMultiplier {
+      chain_id: 1,
+      percent: 100,
+    });
+    assert!(validate_pricing(&duplicate).is_err());
+
+    let mut zero = create_pricing();
+    zero.chain_multipliers[0].percent = 0;
+    assert!(validate_pricing(&zero).is_err());
+
+    let mut margin = create_pricing();
+    margin.margin_percent = MAX_PERCENT + 1;
+    assert!(validate_pricing(&margin).is_err());
+  }
+
+  #[test]
+  fn test_set_pricing_keeps_previous_on_error() {
+    let mut invalid = create_pricing();
+    invalid.margin_percent = MAX_PERCENT + 1;
+
+    assert!(matches!(
+      set_pricing(invalid),
+      AdminResult::Err(AdminError::ImproperConfig(_))
+    ));
+    assert_eq!(get_pricing(), PricingSchedule::default());
+
+    assert_eq!(set_pricing(create_pricing()), AdminResult::Ok);
+    assert_eq!(get_pricing(), create_pricing());
+  }
+}
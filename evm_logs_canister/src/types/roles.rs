@@ -0,0 +1,78 @@
+use std::collections::{HashMap, HashSet};
+
+use candid::{CandidType, Principal};
+use evm_logs_types::Role;
+use serde::{Deserialize, Serialize};
+
+/// Roles granted to principals other than controllers.
+#[derive(Clone, Debug, Default, CandidType, Serialize, Deserialize)]
+pub struct Roles {
+  granted: HashMap<Principal, HashSet<Role>>,
+}
+
+impl Roles {
+  pub fn has(&self, principal: &Principal, role: Role) -> bool {
+    self.granted.get(principal).is_some_and(|roles| roles.contains(&role))
+  }
+
+  pub fn grant(&mut self, principal: Principal, role: Role) {
+    self.granted.entry(principal).or_default().insert(role);
+  }
+
+  /// Returns `false` if the principal didn't have the role.
+  pub fn revoke(&mut self, principal: &Principal, role: Role) -> bool {
+    let Some(roles) = self.granted.get_mut(principal) else {
+      return false;
+    };
+
+    let revoked = roles.remove(&role);
+    if roles.is_empty() {
+      self.granted.remove(principal);
+    }
+    revoked
+  }
+
+  pub fn members(&self, role: Role) -> Vec<Principal> {
+    let mut members: Vec<Principal> = self
+      .granted
+      .iter()
+      .filter(|(_, roles)| roles.contains(&role))
+      .map(|(principal, _)| *principal)
+      .collect();
+    members.sort();
+    members
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_grant_and_revoke() {
+    let mut roles = Roles::default();
+    let principal = Principal::from_slice(&[1]);
+
+    roles.grant(principal, Role::Publisher);
+    assert!(roles.has(&principal, Role::Publisher));
+    assert!(!roles.has(&principal, Role::Operator));
+
+    assert!(roles.revoke(&principal, Role::Publisher));
+    assert!(!roles.has(&principal, Role::Publisher));
+    assert!(!roles.revoke(&principal, Role::Publisher));
+  }
+
+  #[test]
+  fn test_members_of_role() {
+    let mut roles = Roles::default();
+    let first = Principal::from_slice(&[1]);
+    let second = Principal::from_slice(&[2]);
+
+    roles.grant(second, Role::Operator);
+    roles.grant(first, Role::Operator);
+    roles.grant(first, Role::Publisher);
+
+    assert_eq!(roles.members(Role::Operator), vec![first, second]);
+    assert_eq!(roles.members(Role::Publisher), vec![first]);
+  }
+}
@@ -0,0 +1,73 @@
+use std::collections::{HashSet, VecDeque};
+
+use candid::{CandidType, Nat};
+use evm_rpc_types::LogEntry;
+use serde::{Deserialize, Serialize};
+
+/// Identity of a log on a chain: (chain id, block hash, transaction hash, log index).
+pub type LogKey = (u32, Vec<u8>, Vec<u8>, Nat);
+
+/// Bounded window of recently published logs, the oldest ones are forgotten first.
+#[derive(Clone, Debug, Default, CandidType, Serialize, Deserialize)]
+pub struct SeenLogs {
+  order: VecDeque<LogKey>,
+  keys: HashSet<LogKey>,
+}
+
+impl SeenLogs {
+  /// Returns `None` for logs that can't be identified yet (pending ones).
+  pub fn key(chain_id: u32, log: &LogEntry) -> Option<LogKey> {
+    Some((
+      chain_id,
+      log.block_hash.as_ref()?.as_ref().to_vec(),
+      log.transaction_hash.as_ref()?.as_ref().to_vec(),
+      Nat::from(log.log_index.clone()?),
+    ))
+  }
+
+  /// Remembers the key, returns `false` if it's already in the window.
+  pub fn insert(&mut self, key: LogKey, capacity: usize) -> bool {
+    if !self.keys.insert(key.clone()) {
+      return false;
+    }
+
+    self.order.push_back(key);
+    while self.order.len() > capacity {
+      if let Some(oldest) = self.order.pop_front() {
+        self.keys.remove(&oldest);
+      }
+    }
+
+    true
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn key(log_index: u32) -> LogKey {
+    (1, vec![1; 32], vec![2; 32], Nat::from(log_index))
+  }
+
+  #[test]
+  fn test_duplicates_are_rejected() {
+    let mut seen = SeenLogs::default();
+
+    assert!(seen.insert(key(1), 10));
+    assert!(!seen.insert(key(1), 10));
+    assert!(seen.insert(key(2), 10));
+  }
+
+  #[test]
+  fn test_window_forgets_oldest_keys() {
+    let mut seen = SeenLogs::default();
+    for log_index in 0..3 {
+      seen.insert(key(log_index), 2);
+    }
+
+    assert_eq!(seen.order.len(), 2);
+    assert!(seen.insert(key(0), 2));
+    assert!(!seen.insert(key(2), 2));
+  }
+}
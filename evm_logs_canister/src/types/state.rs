@@ -1,21 +1,36 @@
-use std::{collections::HashMap, str::FromStr};
+use std::{
+  collections::{BTreeMap, HashMap, VecDeque},
+  str::FromStr,
+};
 
 use candid::{CandidType, Nat, Principal};
-use evm_logs_types::SubscriptionInfo;
+use evm_logs_types::{Event, PaymentLedger, PricingSchedule, SubscriptionInfo, SubscriptionStats};
 use serde::{Deserialize, Serialize};
 
-use super::{balances::Balances, config::Config};
-use crate::STATE;
+use super::{
+  balance_ledger::BalanceLedger, balances::Balances, config::Config, event_rates::EventRates, roles::Roles,
+  seen_logs::SeenLogs,
+};
+use crate::{STATE, subscription_manager::coalescing::CoalesceBuffer};
 
 #[derive(Clone, CandidType, Serialize, Deserialize, Debug)]
 pub struct State {
   pub evm_rpc_canister: Principal,
   pub proxy_canister: Principal,
   pub estimate_events_num: u32,
-  pub subscriptions: HashMap<Nat, SubscriptionInfo>,
+  pub subscriptions: BTreeMap<Nat, SubscriptionInfo>, // ordered by id for stable paging
   pub subscribers: HashMap<Principal, Vec<Nat>>,
   pub user_balances: Balances,
+  pub balance_ledger: BalanceLedger,
+  pub pricing: PricingSchedule,
+  pub payment_ledgers: Vec<PaymentLedger>,
+  pub roles: Roles,
+  pub event_rates: EventRates,
   pub max_response_bytes: u32,
+  pub coalesce_buffers: HashMap<Nat, CoalesceBuffer>,
+  pub seen_logs: SeenLogs,
+  pub paused_buffers: HashMap<Nat, VecDeque<Event>>,
+  pub subscription_stats: HashMap<Nat, SubscriptionStats>,
   pub test: u32,
 }
 
@@ -35,10 +50,19 @@ impl Default for State {
       evm_rpc_canister: Principal::from_str("aaaaa-aa").expect("Invalid principal"),
       proxy_canister: Principal::from_str("aaaaa-aa").expect("Invalid principal"),
       estimate_events_num: 30,
-      subscriptions: HashMap::new(),
+      subscriptions: BTreeMap::new(),
       subscribers: HashMap::new(),
       user_balances: Balances::default(),
+      balance_ledger: BalanceLedger::default(),
+      pricing: PricingSchedule::default(),
+      payment_ledgers: Vec::new(),
+      roles: Roles::default(),
+      event_rates: EventRates::default(),
       max_response_bytes: 1_000_000,
+      coalesce_buffers: HashMap::new(),
+      seen_logs: SeenLogs::default(),
+      paused_buffers: HashMap::new(),
+      subscription_stats: HashMap::new(),
       test: 0,
     }
   }
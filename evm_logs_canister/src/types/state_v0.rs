@@ -0,0 +1,74 @@
+use std::collections::HashMap;
+
+use candid::{CandidType, Nat, Principal};
+use evm_logs_types::{DeliveryMode, Filter, Map, SubscriptionInfo, SubscriptionStatus};
+use serde::Deserialize;
+
+use super::{balances::Balances, state::State};
+use crate::constants::DEFAULT_FAILURE_THRESHOLD;
+
+/// `State` as saved by canisters deployed before subscriptions got delivery modes, owners and statuses.
+/// Candid fills in only missing `opt` fields, so it's decoded as is and migrated to the current `State`.
+#[derive(CandidType, Deserialize)]
+pub struct StateV0 {
+  pub evm_rpc_canister: Principal,
+  pub proxy_canister: Principal,
+  pub estimate_events_num: u32,
+  pub subscriptions: HashMap<Nat, SubscriptionInfoV0>,
+  pub subscribers: HashMap<Principal, Vec<Nat>>,
+  pub user_balances: Balances,
+  pub max_response_bytes: u32,
+  pub test: u32,
+}
+
+#[derive(CandidType, Deserialize)]
+pub struct SubscriptionInfoV0 {
+  pub subscription_id: Nat,
+  pub subscriber_principal: Principal,
+  pub chain_id: u32,
+  pub filter: Filter,
+  pub stats: Vec<Map>,
+}
+
+impl From<SubscriptionInfoV0> for SubscriptionInfo {
+  fn from(subscription: SubscriptionInfoV0) -> Self {
+    Self {
+      subscription_id: subscription.subscription_id,
+      subscriber_principal: subscription.subscriber_principal,
+      owner: subscription.subscriber_principal, // the only principal that is known to have agreed to pay for it
+      managers: vec![],
+      chain_id: subscription.chain_id,
+      filter: subscription.filter,
+      stats: subscription.stats,
+      delivery: DeliveryMode::Immediate,
+      status: SubscriptionStatus::Active,
+      expiry: None,
+      remaining_events: None,
+      memo: None,
+      labels: vec![],
+      callback: None,
+      failure_threshold: DEFAULT_FAILURE_THRESHOLD,
+    }
+  }
+}
+
+/// Everything introduced since starts empty or with its defaults.
+impl From<StateV0> for State {
+  fn from(state: StateV0) -> Self {
+    Self {
+      evm_rpc_canister: state.evm_rpc_canister,
+      proxy_canister: state.proxy_canister,
+      estimate_events_num: state.estimate_events_num,
+      subscriptions: state
+        .subscriptions
+        .into_iter()
+        .map(|(id, subscription)| (id, subscription.into()))
+        .collect(),
+      subscribers: state.subscribers,
+      user_balances: state.user_balances,
+      max_response_bytes: state.max_response_bytes,
+      test: state.test,
+      ..State::default()
+    }
+  }
+}
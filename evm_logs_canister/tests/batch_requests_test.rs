@@ -13,7 +13,7 @@ use crate::test_config::TestConfig;
 
 #[tokio::test]
 async fn batch_requests_test() -> Result<()> {
-  let (mut pic, evm_logs_can_id, evm_rpc_mocked_can_id, proxy_can_id, cycles_wallet_can_id) =
+  let (mut pic, evm_logs_can_id, evm_rpc_mocked_can_id, proxy_can_id, _cycles_wallet_can_id) =
     init_pocket_ic_all_cans().await?;
   let subscriber_can_id = init_pocket_ic_subscribers(&mut pic, 1).await?[0];
 
@@ -34,27 +34,17 @@ async fn batch_requests_test() -> Result<()> {
       filter: random_filter,
       memo: None,
       canister_to_top_up: subscriber_can_id,
+      delivery: None,
+      managers: None,
+      expiry: None,
+      max_events: None,
+      labels: None,
+      callback: None,
+      failure_threshold: None,
     };
 
-    let sub_reg_encoded = candid::encode_args((sub_registration,))?;
-
-    let call_args = WalletCall128Args {
-      canister: evm_logs_can_id,
-      method_name: "subscribe".to_string(),
-      args: sub_reg_encoded,
-      cycles: 2_000_000_000_000u128.into(),
-    };
-
-    let bytes = candid::encode_args((call_args,))?;
-
-    match pic
-      .update_call(cycles_wallet_can_id, Principal::anonymous(), "wallet_call128", bytes)
-      .await
-    {
-      Ok(WasmResult::Reply(data)) => ic_cdk::println!("Subscription successful: {:?}", data),
-      Ok(WasmResult::Reject(err)) => panic!("Subscription rejected: {:?}", err),
-      Err(e) => panic!("Subscription call error: {:?}", e),
-    }
+    let sub_id = subscribe_from_subscriber(&pic, evm_logs_can_id, sub_registration, 500_000_000_000).await?;
+    ic_cdk::println!("Subscription successful: {:?}", sub_id);
   }
 
   // Waiting when evm_logs_canister will fetch logs from mocked evm rpc canister and send it so subscribers
@@ -152,7 +142,12 @@ async fn init_pocket_ic_all_cans() -> Result<(PocketIc, CanisterId, CanisterId,
 
   pic.add_cycles(proxy_can_id, DEFAULT_CYCLES_VALUE).await;
   let proxy_wasm_bytes = tokio::fs::read(test_config.proxy_canister_wasm_path).await?;
-  pic.install_canister(proxy_can_id, proxy_wasm_bytes, vec![], None).await;
+  let proxy_init_args = candid::encode_args((ProxyConfig {
+    evm_logs_canister: evm_logs_can_id,
+  },))?;
+  pic
+    .install_canister(proxy_can_id, proxy_wasm_bytes, proxy_init_args, None)
+    .await;
 
   pic.add_cycles(evm_logs_can_id, DEFAULT_CYCLES_VALUE).await;
   let evm_logs_wasm_bytes = tokio::fs::read(test_config.evm_logs_canister_wasm_path).await?;
@@ -0,0 +1,269 @@
+mod test_config;
+
+use anyhow::Result;
+use candid::{self, CandidType, Deserialize, Nat, Principal, Reserved};
+use evm_logs_types::{AdminResult, DepositError, DepositResult, PaymentLedger};
+use pocket_ic::{WasmResult, management_canister::CanisterId, nonblocking::PocketIc};
+
+use crate::test_config::TestConfig;
+
+const DEFAULT_CYCLES_VALUE: u128 = 4_000_000_000_000;
+const TRANSFER_FEE: u64 = 10_000;
+const INITIAL_TOKENS: u64 = 1_000_000_000;
+const CYCLES_PER_TOKEN: u64 = 3;
+
+#[derive(CandidType, Deserialize)]
+struct EvmLogsInitArgs {
+  evm_rpc_canister: Principal,
+  proxy_canister: Principal,
+  estimate_events_num: u32,
+  max_response_bytes: u32,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+struct Account {
+  owner: Principal,
+  subaccount: Option<Vec<u8>>,
+}
+
+/// Init arguments of the ICRC-1 ledger, optional fields left out
+#[derive(CandidType, Deserialize)]
+enum LedgerArg {
+  Init(LedgerInitArgs),
+}
+
+#[derive(CandidType, Deserialize)]
+struct LedgerInitArgs {
+  minting_account: Account,
+  transfer_fee: Nat,
+  token_symbol: String,
+  token_name: String,
+  metadata: Vec<(String, LedgerMetadataValue)>,
+  initial_balances: Vec<(Account, Nat)>,
+  feature_flags: Option<LedgerFeatureFlags>,
+  archive_options: LedgerArchiveOptions,
+}
+
+#[derive(CandidType, Deserialize)]
+enum LedgerMetadataValue {
+  Text(String),
+}
+
+#[derive(CandidType, Deserialize)]
+struct LedgerFeatureFlags {
+  icrc2: bool,
+}
+
+#[derive(CandidType, Deserialize)]
+struct LedgerArchiveOptions {
+  num_blocks_to_archive: u64,
+  trigger_threshold: u64,
+  controller_id: Principal,
+}
+
+#[derive(CandidType, Deserialize)]
+struct ApproveArgs {
+  from_subaccount: Option<Vec<u8>>,
+  spender: Account,
+  amount: Nat,
+  expected_allowance: Option<Nat>,
+  expires_at: Option<u64>,
+  fee: Option<Nat>,
+  memo: Option<Vec<u8>>,
+  created_at_time: Option<u64>,
+}
+
+#[tokio::test]
+async fn test_deposit_tokens_approved_on_ledger() -> Result<()> {
+  let (pic, evm_logs_can_id, ledger_can_id, payer) = init_pocket_ic_with_ledger().await?;
+  let subscriber = Principal::self_authenticating(b"subscriber");
+
+  // the canister is installed by the anonymous principal, which controls it
+  let payment_ledger = PaymentLedger {
+    ledger: ledger_can_id,
+    token_symbol: "TKN".to_string(),
+    cycles_numerator: Nat::from(CYCLES_PER_TOKEN),
+    cycles_denominator: Nat::from(1u32),
+  };
+  let set_result: AdminResult = update(
+    &pic,
+    evm_logs_can_id,
+    Principal::anonymous(),
+    "set_payment_ledger",
+    candid::encode_one(payment_ledger)?,
+  )
+  .await?;
+  assert_eq!(set_result, AdminResult::Ok);
+
+  // the payer allows the evm-logs-canister to take the deposit plus the transfer fee
+  let approve_args = ApproveArgs {
+    from_subaccount: None,
+    spender: Account {
+      owner: evm_logs_can_id,
+      subaccount: None,
+    },
+    amount: Nat::from(1_000_000u64 + TRANSFER_FEE),
+    expected_allowance: None,
+    expires_at: None,
+    fee: None,
+    memo: None,
+    created_at_time: None,
+  };
+  let approve_result: Result<Nat, Reserved> = update(
+    &pic,
+    ledger_can_id,
+    payer,
+    "icrc2_approve",
+    candid::encode_one(approve_args)?,
+  )
+  .await?;
+  assert!(approve_result.is_ok(), "Approve failed");
+
+  let deposit_result: DepositResult = update(
+    &pic,
+    evm_logs_can_id,
+    payer,
+    "deposit",
+    candid::encode_args((ledger_can_id, Nat::from(1_000_000u64), subscriber))?,
+  )
+  .await?;
+  match deposit_result {
+    DepositResult::Ok(cycles) => assert_eq!(cycles, Nat::from(1_000_000u64 * CYCLES_PER_TOKEN)),
+    DepositResult::Err(err) => panic!("Deposit failed: {:?}", err),
+  }
+
+  let balance: Nat = query(&pic, evm_logs_can_id, "get_balance", candid::encode_one(subscriber)?).await?;
+  assert_eq!(balance, Nat::from(1_000_000u64 * CYCLES_PER_TOKEN));
+
+  let canister_tokens: Nat = query(
+    &pic,
+    ledger_can_id,
+    "icrc1_balance_of",
+    candid::encode_one(Account {
+      owner: evm_logs_can_id,
+      subaccount: None,
+    })?,
+  )
+  .await?;
+  assert_eq!(canister_tokens, Nat::from(1_000_000u64));
+
+  // the allowance is used up
+  let second_deposit: DepositResult = update(
+    &pic,
+    evm_logs_can_id,
+    payer,
+    "deposit",
+    candid::encode_args((ledger_can_id, Nat::from(1_000_000u64), subscriber))?,
+  )
+  .await?;
+  assert!(matches!(
+    second_deposit,
+    DepositResult::Err(DepositError::TransferFailed(_))
+  ));
+
+  Ok(())
+}
+
+#[tokio::test]
+async fn test_deposit_from_unsupported_ledger() -> Result<()> {
+  let (pic, evm_logs_can_id, ledger_can_id, payer) = init_pocket_ic_with_ledger().await?;
+
+  let deposit_result: DepositResult = update(
+    &pic,
+    evm_logs_can_id,
+    payer,
+    "deposit",
+    candid::encode_args((ledger_can_id, Nat::from(1_000u64), payer))?,
+  )
+  .await?;
+  assert!(matches!(
+    deposit_result,
+    DepositResult::Err(DepositError::UnsupportedLedger)
+  ));
+
+  Ok(())
+}
+
+async fn update<T: candid::CandidType + for<'de> candid::Deserialize<'de>>(
+  pic: &PocketIc,
+  canister_id: CanisterId,
+  sender: Principal,
+  method: &str,
+  args: Vec<u8>,
+) -> Result<T> {
+  match pic.update_call(canister_id, sender, method, args).await {
+    Ok(WasmResult::Reply(data)) => Ok(candid::decode_one(&data)?),
+    Ok(WasmResult::Reject(err)) => anyhow::bail!("{} rejected: {:?}", method, err),
+    Err(e) => anyhow::bail!("{} call error: {:?}", method, e),
+  }
+}
+
+async fn query<T: candid::CandidType + for<'de> candid::Deserialize<'de>>(
+  pic: &PocketIc,
+  canister_id: CanisterId,
+  method: &str,
+  args: Vec<u8>,
+) -> Result<T> {
+  match pic.query_call(canister_id, Principal::anonymous(), method, args).await {
+    Ok(WasmResult::Reply(data)) => Ok(candid::decode_one(&data)?),
+    Ok(WasmResult::Reject(err)) => anyhow::bail!("{} rejected: {:?}", method, err),
+    Err(e) => anyhow::bail!("{} call error: {:?}", method, e),
+  }
+}
+
+/// Initializes PocketIc with the evm-logs-canister and an ICRC-2 ledger in which the returned payer holds tokens.
+/// Returns (pic, evm_logs_can_id, ledger_can_id, payer).
+async fn init_pocket_ic_with_ledger() -> Result<(PocketIc, CanisterId, CanisterId, Principal)> {
+  let pic = PocketIc::new().await;
+  let test_config = TestConfig::new()?;
+  let payer = Principal::self_authenticating(b"payer");
+
+  let evm_logs_can_id = pic.create_canister().await;
+  let ledger_can_id = pic.create_canister().await;
+  // neither is called without subscriptions
+  let evm_rpc_can_id = pic.create_canister().await;
+  let proxy_can_id = pic.create_canister().await;
+
+  pic.add_cycles(ledger_can_id, DEFAULT_CYCLES_VALUE).await;
+  let ledger_wasm_bytes = tokio::fs::read(test_config.icrc1_ledger_wasm_path).await?;
+  let ledger_init_args = candid::encode_one(LedgerArg::Init(LedgerInitArgs {
+    minting_account: Account {
+      owner: Principal::self_authenticating(b"minter"),
+      subaccount: None,
+    },
+    transfer_fee: Nat::from(TRANSFER_FEE),
+    token_symbol: "TKN".to_string(),
+    token_name: "Test token".to_string(),
+    metadata: vec![("icrc1:logo".to_string(), LedgerMetadataValue::Text(String::new()))],
+    initial_balances: vec![(
+      Account {
+        owner: payer,
+        subaccount: None,
+      },
+      Nat::from(INITIAL_TOKENS),
+    )],
+    feature_flags: Some(LedgerFeatureFlags { icrc2: true }),
+    archive_options: LedgerArchiveOptions {
+      num_blocks_to_archive: 1_000,
+      trigger_threshold: 2_000,
+      controller_id: Principal::anonymous(),
+    },
+  }))?;
+  pic
+    .install_canister(ledger_can_id, ledger_wasm_bytes, ledger_init_args, None)
+    .await;
+
+  pic.add_cycles(evm_logs_can_id, DEFAULT_CYCLES_VALUE).await;
+  let evm_logs_wasm_bytes = tokio::fs::read(test_config.evm_logs_canister_wasm_path).await?;
+  let init_args = candid::encode_args((EvmLogsInitArgs {
+    evm_rpc_canister: evm_rpc_can_id,
+    proxy_canister: proxy_can_id,
+    estimate_events_num: 5,
+    max_response_bytes: 10000,
+  },))?;
+  pic
+    .install_canister(evm_logs_can_id, evm_logs_wasm_bytes, init_args, None)
+    .await;
+
+  Ok((pic, evm_logs_can_id, ledger_can_id, payer))
+}
@@ -1,9 +1,11 @@
 use std::str::FromStr;
 
+use anyhow::{Result, bail};
 use candid::{CandidType, Deserialize, Nat, Principal};
-use evm_logs_types::Filter;
+use evm_logs_types::{Filter, RegisterSubscriptionResult, SubscriptionRegistration};
 use evm_rpc_types::{Hex20, Hex32};
 use getrandom::getrandom;
+use pocket_ic::{WasmResult, nonblocking::PocketIc};
 
 pub const DEFAULT_CYCLES_VALUE: u128 = 4_000_000_000_000;
 
@@ -16,16 +18,37 @@ pub struct EvmLogsInitArgs {
 }
 
 #[derive(CandidType, Deserialize)]
-pub struct WalletCall128Args {
-  pub canister: Principal,
-  pub method_name: String,
-  pub args: Vec<u8>,
-  pub cycles: Nat,
+pub struct EvmRpcMockedConfig {
+  pub evm_logs_canister_id: Principal,
 }
 
 #[derive(CandidType, Deserialize)]
-pub struct EvmRpcMockedConfig {
-  pub evm_logs_canister_id: Principal,
+pub struct ProxyConfig {
+  pub evm_logs_canister: Principal,
+}
+
+/// Registers the subscription from its `canister_to_top_up` test canister, which pays `cycles` for it.
+/// Only the subscriber itself (or its delegates) may subscribe on its behalf.
+pub async fn subscribe_from_subscriber(
+  pic: &PocketIc,
+  evm_logs_can_id: Principal,
+  registration: SubscriptionRegistration,
+  cycles: u128,
+) -> Result<Nat> {
+  let subscriber_can_id = registration.canister_to_top_up;
+  let args = candid::encode_args((evm_logs_can_id, registration, cycles))?;
+
+  match pic
+    .update_call(subscriber_can_id, Principal::anonymous(), "register_subscription", args)
+    .await
+  {
+    Ok(WasmResult::Reply(data)) => match candid::decode_one(&data)? {
+      RegisterSubscriptionResult::Ok(sub_id) => Ok(sub_id),
+      RegisterSubscriptionResult::Err(err) => bail!("Subscription failed: {:?}", err),
+    },
+    Ok(WasmResult::Reject(err)) => bail!("Subscription rejected: {:?}", err),
+    Err(e) => bail!("Subscription call error: {:?}", e),
+  }
 }
 
 pub fn generate_random_filter() -> Filter {
@@ -41,5 +64,7 @@ pub fn generate_random_filter() -> Filter {
   Filter {
     address: Hex20::from_str(&address).unwrap(),
     topics: Some(vec![vec![Hex32::from_str(&topic).unwrap()]]),
+    predicate: None,
+    data_masks: None,
   }
 }
@@ -16,7 +16,7 @@ async fn test_main_workflow_with_bunch_subscribers() -> Result<()> {
   // This hashmap will store the subscriber canister ID -> filter
   let mut subscriber_filters = HashMap::<Principal, Filter>::new();
 
-  let (mut pic, evm_logs_can_id, _evm_rpc_mocked_can_id, _proxy_can_id, cycles_wallet_can_id) =
+  let (mut pic, evm_logs_can_id, _evm_rpc_mocked_can_id, _proxy_can_id, _cycles_wallet_can_id) =
     init_pocket_ic_all_cans().await?;
 
   let num_subscribers = 2;
@@ -34,27 +34,17 @@ async fn test_main_workflow_with_bunch_subscribers() -> Result<()> {
       filter,
       memo: None,
       canister_to_top_up: *subscriber_canister_id,
+      delivery: None,
+      managers: None,
+      expiry: None,
+      max_events: None,
+      labels: None,
+      callback: None,
+      failure_threshold: None,
     };
 
-    let sub_reg_encoded = candid::encode_args((sub_registration,))?;
-
-    let call_args = WalletCall128Args {
-      canister: evm_logs_can_id,
-      method_name: "subscribe".to_string(),
-      args: sub_reg_encoded,
-      cycles: 2_000_000_000_000u128.into(),
-    };
-
-    let bytes = candid::encode_args((call_args,))?;
-
-    match pic
-      .update_call(cycles_wallet_can_id, Principal::anonymous(), "wallet_call128", bytes)
-      .await
-    {
-      Ok(WasmResult::Reply(data)) => ic_cdk::println!("Subscription successful: {:?}", data),
-      Ok(WasmResult::Reject(err)) => panic!("Subscription rejected: {:?}", err),
-      Err(e) => panic!("Subscription call error: {:?}", e),
-    }
+    let sub_id = subscribe_from_subscriber(&pic, evm_logs_can_id, sub_registration, 500_000_000_000).await?;
+    ic_cdk::println!("Subscription successful: {:?}", sub_id);
   }
 
   // Verify subscription count
@@ -158,7 +148,12 @@ async fn init_pocket_ic_all_cans() -> anyhow::Result<(PocketIc, CanisterId, Cani
 
   pic.add_cycles(proxy_can_id, DEFAULT_CYCLES_VALUE).await;
   let proxy_wasm_bytes = tokio::fs::read(test_config.proxy_canister_wasm_path).await?;
-  pic.install_canister(proxy_can_id, proxy_wasm_bytes, vec![], None).await;
+  let proxy_init_args = candid::encode_args((ProxyConfig {
+    evm_logs_canister: evm_logs_can_id,
+  },))?;
+  pic
+    .install_canister(proxy_can_id, proxy_wasm_bytes, proxy_init_args, None)
+    .await;
 
   pic.add_cycles(evm_logs_can_id, DEFAULT_CYCLES_VALUE).await;
   let evm_logs_wasm_bytes = tokio::fs::read(test_config.evm_logs_canister_wasm_path).await?;
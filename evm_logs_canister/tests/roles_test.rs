@@ -0,0 +1,94 @@
+mod test_config;
+
+use anyhow::Result;
+use candid::{self, CandidType, Deserialize, Principal};
+use evm_logs_types::{AdminResult, Event, Role};
+use pocket_ic::{WasmResult, management_canister::CanisterId, nonblocking::PocketIc};
+
+use crate::test_config::TestConfig;
+
+const DEFAULT_CYCLES_VALUE: u128 = 4_000_000_000_000;
+
+#[derive(CandidType, Deserialize)]
+struct EvmLogsInitArgs {
+  evm_rpc_canister: Principal,
+  proxy_canister: Principal,
+  estimate_events_num: u32,
+  max_response_bytes: u32,
+}
+
+#[tokio::test]
+async fn test_publish_events_requires_publisher_role() -> Result<()> {
+  let (pic, evm_logs_can_id, controller) = init_pocket_ic().await?;
+  let publisher = Principal::self_authenticating(b"publisher");
+
+  let publish = |sender: Principal| {
+    let pic = &pic;
+    async move {
+      pic
+        .update_call(
+          evm_logs_can_id,
+          sender,
+          "publish_events",
+          candid::encode_one(Vec::<Event>::new()).unwrap(),
+        )
+        .await
+    }
+  };
+
+  let result = publish(publisher).await;
+  assert!(
+    !matches!(result, Ok(WasmResult::Reply(_))),
+    "publish_events of a non-controller without the role was accepted: {:?}",
+    result
+  );
+
+  let grant_result = pic
+    .update_call(
+      evm_logs_can_id,
+      controller,
+      "grant_role",
+      candid::encode_args((publisher, Role::Publisher))?,
+    )
+    .await;
+  let Ok(WasmResult::Reply(data)) = grant_result else {
+    panic!("grant_role failed: {:?}", grant_result);
+  };
+  assert!(matches!(candid::decode_one(&data)?, AdminResult::Ok));
+
+  let result = publish(publisher).await;
+  assert!(
+    matches!(result, Ok(WasmResult::Reply(_))),
+    "publish_events of a publisher was rejected: {:?}",
+    result
+  );
+
+  Ok(())
+}
+
+/// Initializes PocketIc with the evm-logs-canister.
+/// Returns (pic, evm_logs_can_id, controller of the canister).
+async fn init_pocket_ic() -> Result<(PocketIc, CanisterId, Principal)> {
+  let pic = PocketIc::new().await;
+  let test_config = TestConfig::new()?;
+  let controller = Principal::anonymous();
+
+  let evm_logs_can_id = pic.create_canister_with_settings(Some(controller), None).await;
+  // neither is called, events are published directly
+  let evm_rpc_can_id = pic.create_canister().await;
+  let proxy_can_id = pic.create_canister().await;
+
+  pic.add_cycles(evm_logs_can_id, DEFAULT_CYCLES_VALUE).await;
+  let evm_logs_wasm_bytes = tokio::fs::read(test_config.evm_logs_canister_wasm_path).await?;
+  let init_args = candid::encode_args((EvmLogsInitArgs {
+    evm_rpc_canister: evm_rpc_can_id,
+    proxy_canister: proxy_can_id,
+    estimate_events_num: 5,
+    max_response_bytes: 10000,
+  },))?;
+  pic
+    .install_canister(evm_logs_can_id, evm_logs_wasm_bytes, init_args, Some(controller))
+    .await;
+
+  Ok((pic, evm_logs_can_id, controller))
+}
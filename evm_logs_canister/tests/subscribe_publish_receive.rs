@@ -19,7 +19,7 @@ const TOPIC1_HEX32: &str = "0xc42079f94a6350d7e6235f29174924f928cc2ac818eb64fed8
 
 #[tokio::test]
 async fn test_event_publishing_and_notification_delivery() -> Result<()> {
-  let (mut pic, evm_logs_can_id, _evm_rpc_mocked_can_id, _proxy_can_id, cycles_wallet_can_id) =
+  let (mut pic, evm_logs_can_id, _evm_rpc_mocked_can_id, _proxy_can_id, _cycles_wallet_can_id) =
     init_pocket_ic_all_cans().await?;
   let subscriber_can_id = init_pocket_ic_subscribers(&mut pic, 1).await?[0];
 
@@ -32,37 +32,20 @@ async fn test_event_publishing_and_notification_delivery() -> Result<()> {
     filter: Filter {
       address: addr1.clone(), // Example address
       topics: Some(vec![vec![topic1.clone()]]),
+      predicate: None,
+      data_masks: None,
     },
     memo: None,
     canister_to_top_up: subscriber_can_id,
+    delivery: None,
+    managers: None,
+    expiry: None,
+    max_events: None,
+    labels: None,
+    callback: None,
+    failure_threshold: None,
   };
-  let sub_reg_encoded = candid::encode_args((sub_registration,))?;
-
-  let call_args = WalletCall128Args {
-    canister: evm_logs_can_id,
-    method_name: "subscribe".to_string(),
-    args: sub_reg_encoded,
-    cycles: 2_000_000_000_000u128.into(),
-  };
-  let bytes = candid::encode_args((call_args,)).expect("Failed to encode wallet_call128 args");
-
-  let subscribe_via_cycles_wallet = pic
-    .update_call(cycles_wallet_can_id, Principal::anonymous(), "wallet_call128", bytes)
-    .await;
-
-  match subscribe_via_cycles_wallet {
-    Ok(WasmResult::Reply(data)) => {
-      ic_cdk::println!("Reply: {:?}", data);
-    }
-    Ok(WasmResult::Reject(err)) => {
-      panic!("Subscription rejected: {:?}", err);
-    }
-    Err(e) => {
-      panic!("Subscription call error: {:?}", e);
-    }
-  }
-
-  // TODO Check the subscription registration result
+  subscribe_from_subscriber(&pic, evm_logs_can_id, sub_registration, 2_000_000_000_000).await?;
 
   // Publish an event
   let event = Event {
@@ -163,7 +146,12 @@ async fn init_pocket_ic_all_cans() -> Result<(PocketIc, CanisterId, CanisterId,
 
   pic.add_cycles(proxy_can_id, DEFAULT_CYCLES_VALUE).await;
   let proxy_wasm_bytes = tokio::fs::read(test_config.proxy_canister_wasm_path).await?;
-  pic.install_canister(proxy_can_id, proxy_wasm_bytes, vec![], None).await;
+  let proxy_init_args = candid::encode_args((ProxyConfig {
+    evm_logs_canister: evm_logs_can_id,
+  },))?;
+  pic
+    .install_canister(proxy_can_id, proxy_wasm_bytes, proxy_init_args, None)
+    .await;
 
   pic.add_cycles(evm_logs_can_id, DEFAULT_CYCLES_VALUE).await;
   let evm_logs_wasm_bytes = tokio::fs::read(test_config.evm_logs_canister_wasm_path).await?;
@@ -14,6 +14,7 @@ pub struct TestConfig {
   pub cycles_wallet_wasm_path: String,
   pub proxy_canister_wasm_path: String,
   pub evm_rpc_mocked_wasm_path: String,
+  pub icrc1_ledger_wasm_path: String,
 }
 impl TestConfig {
   pub fn new() -> Result<Self> {
@@ -52,6 +53,7 @@ impl TestConfig {
             path_to_append.to_str().unwrap(),
             config.evm_rpc_mocked_wasm_path
           ),
+          icrc1_ledger_wasm_path: format!("{}/{}", path_to_append.to_str().unwrap(), config.icrc1_ledger_wasm_path),
         }),
         Err(e) => {
           bail!(
@@ -0,0 +1,136 @@
+mod test_config;
+
+use anyhow::Result;
+use candid::{self, CandidType, Deserialize, Nat, Principal};
+use evm_logs_types::{WithdrawBalanceError, WithdrawBalanceResult};
+use pocket_ic::{WasmResult, management_canister::CanisterId, nonblocking::PocketIc};
+
+use crate::test_config::TestConfig;
+
+const DEFAULT_CYCLES_VALUE: u128 = 4_000_000_000_000;
+const TOP_UP_CYCLES: u128 = 1_000_000_000_000;
+// execution of the calls is paid from the canister balance too
+const EXECUTION_CYCLES_MARGIN: u128 = 10_000_000_000;
+
+#[derive(CandidType, Deserialize)]
+struct EvmLogsInitArgs {
+  evm_rpc_canister: Principal,
+  proxy_canister: Principal,
+  estimate_events_num: u32,
+  max_response_bytes: u32,
+}
+
+#[tokio::test]
+async fn test_top_up_accepts_the_credited_cycles() -> Result<()> {
+  let (pic, evm_logs_can_id, subscriber_can_id) = init_pocket_ic().await?;
+
+  let canister_cycles_before = pic.cycle_balance(evm_logs_can_id).await;
+  let top_up_result = pic
+    .update_call(
+      subscriber_can_id,
+      Principal::anonymous(),
+      "top_up_evm_logs_canister",
+      candid::encode_args((evm_logs_can_id, TOP_UP_CYCLES))?,
+    )
+    .await;
+  assert!(
+    matches!(top_up_result, Ok(WasmResult::Reply(_))),
+    "top_up_evm_logs_canister failed: {:?}",
+    top_up_result
+  );
+
+  let canister_cycles_after = pic.cycle_balance(evm_logs_can_id).await;
+  assert!(
+    canister_cycles_after + EXECUTION_CYCLES_MARGIN > canister_cycles_before + TOP_UP_CYCLES,
+    "the canister holds {} cycles after a top-up of {}, {} before",
+    canister_cycles_after,
+    TOP_UP_CYCLES,
+    canister_cycles_before
+  );
+
+  let balance_data = pic
+    .query_call(
+      evm_logs_can_id,
+      Principal::anonymous(),
+      "get_balance",
+      candid::encode_one(subscriber_can_id)?,
+    )
+    .await;
+  let Ok(WasmResult::Reply(data)) = balance_data else {
+    panic!("get_balance failed: {:?}", balance_data);
+  };
+  let balance: Nat = candid::decode_one(&data)?;
+  assert_eq!(balance, Nat::from(TOP_UP_CYCLES));
+
+  // nothing but the accepted cycles can be withdrawn
+  let withdraw = |amount: u128| {
+    let pic = &pic;
+    async move {
+      let result = pic
+        .update_call(
+          evm_logs_can_id,
+          subscriber_can_id,
+          "withdraw_balance",
+          candid::encode_args((Nat::from(amount), subscriber_can_id)).unwrap(),
+        )
+        .await;
+      let Ok(WasmResult::Reply(data)) = result else {
+        panic!("withdraw_balance failed: {:?}", result);
+      };
+      candid::decode_one::<WithdrawBalanceResult>(&data).unwrap()
+    }
+  };
+
+  let result = withdraw(TOP_UP_CYCLES + 1).await;
+  assert!(
+    matches!(
+      &result,
+      WithdrawBalanceResult::Err(WithdrawBalanceError::InsufficientBalance { balance })
+        if *balance == TOP_UP_CYCLES
+    ),
+    "withdrawing more than was accepted: {:?}",
+    result
+  );
+
+  let result = withdraw(TOP_UP_CYCLES).await;
+  assert!(
+    matches!(&result, WithdrawBalanceResult::Ok(amount) if *amount == TOP_UP_CYCLES),
+    "withdrawing the accepted cycles: {:?}",
+    result
+  );
+
+  Ok(())
+}
+
+/// Initializes PocketIc with the evm-logs-canister and a test canister topping up its balance there.
+/// Returns (pic, evm_logs_can_id, subscriber_can_id).
+async fn init_pocket_ic() -> Result<(PocketIc, CanisterId, CanisterId)> {
+  let pic = PocketIc::new().await;
+  let test_config = TestConfig::new()?;
+
+  let evm_logs_can_id = pic.create_canister().await;
+  // neither is called, no subscription is registered
+  let evm_rpc_can_id = pic.create_canister().await;
+  let proxy_can_id = pic.create_canister().await;
+
+  pic.add_cycles(evm_logs_can_id, DEFAULT_CYCLES_VALUE).await;
+  let evm_logs_wasm_bytes = tokio::fs::read(test_config.evm_logs_canister_wasm_path).await?;
+  let init_args = candid::encode_args((EvmLogsInitArgs {
+    evm_rpc_canister: evm_rpc_can_id,
+    proxy_canister: proxy_can_id,
+    estimate_events_num: 5,
+    max_response_bytes: 10000,
+  },))?;
+  pic
+    .install_canister(evm_logs_can_id, evm_logs_wasm_bytes, init_args, None)
+    .await;
+
+  let subscriber_can_id = pic.create_canister().await;
+  pic.add_cycles(subscriber_can_id, DEFAULT_CYCLES_VALUE).await;
+  let subscriber_wasm_bytes = tokio::fs::read(test_config.test_canister_wasm_path).await?;
+  pic
+    .install_canister(subscriber_can_id, subscriber_wasm_bytes, vec![], None)
+    .await;
+
+  Ok((pic, evm_logs_can_id, subscriber_can_id))
+}
@@ -0,0 +1,234 @@
+mod test_config;
+
+use std::str::FromStr;
+
+use anyhow::Result;
+use candid::{self, CandidType, Deserialize, Nat, Principal};
+use evm_logs_types::{
+  AdminResult, ConfigUpdate, Filter, RegisterSubscriptionResult, SubscriptionInfo, SubscriptionRegistration,
+};
+use evm_rpc_types::{Hex20, Hex32};
+use pocket_ic::{WasmResult, management_canister::CanisterId, nonblocking::PocketIc};
+
+use crate::test_config::TestConfig;
+
+const DEFAULT_CYCLES_VALUE: u128 = 4_000_000_000_000;
+const ADDR_HEX20: &str = "0xb2cc224c1c9feE385f8ad6a55b4d94E92359DC59";
+const TOPIC_HEX32: &str = "0xc42079f94a6350d7e6235f29174924f928cc2ac818eb64fed8004e115fbcca67";
+
+#[derive(CandidType, Deserialize)]
+struct EvmLogsInitArgs {
+  evm_rpc_canister: Principal,
+  proxy_canister: Principal,
+  estimate_events_num: u32,
+  max_response_bytes: u32,
+}
+
+#[tokio::test]
+async fn test_upgrade_argument_updates_config() -> Result<()> {
+  let test_config = TestConfig::new()?;
+  let (pic, evm_logs_can_id) = init_pocket_ic_with_wasm(&test_config.evm_logs_canister_wasm_path).await?;
+  let evm_logs_wasm_bytes = tokio::fs::read(&test_config.evm_logs_canister_wasm_path).await?;
+  let sub_id = subscribe(
+    &pic,
+    evm_logs_can_id,
+    registration(Principal::self_authenticating(b"subscriber")),
+  )
+  .await?;
+
+  // without an argument the config is kept, 5 events of 800 bytes fit into 4000 bytes
+  pic
+    .upgrade_canister(
+      evm_logs_can_id,
+      evm_logs_wasm_bytes.clone(),
+      candid::encode_args(())?,
+      None,
+    )
+    .await
+    .expect("Upgrade without an argument failed");
+  assert!(update_config_succeeds(&pic, evm_logs_can_id, 4_000).await?);
+
+  // responses for 10 events of 800 bytes no longer fit into 4000 bytes
+  let config_update = ConfigUpdate {
+    estimate_events_num: Some(10),
+    max_response_bytes: Some(10_000),
+    ..Default::default()
+  };
+  pic
+    .upgrade_canister(
+      evm_logs_can_id,
+      evm_logs_wasm_bytes.clone(),
+      candid::encode_one(Some(config_update))?,
+      None,
+    )
+    .await
+    .expect("Upgrade with a config update failed");
+  assert!(!update_config_succeeds(&pic, evm_logs_can_id, 4_000).await?);
+
+  let invalid_update = ConfigUpdate {
+    estimate_events_num: Some(0),
+    ..Default::default()
+  };
+  let upgrade_result = pic
+    .upgrade_canister(
+      evm_logs_can_id,
+      evm_logs_wasm_bytes,
+      candid::encode_one(Some(invalid_update))?,
+      None,
+    )
+    .await;
+  assert!(
+    upgrade_result.is_err(),
+    "Upgrade with an invalid config update succeeded"
+  );
+
+  // the failed upgrade keeps the previous state and config
+  let subscriptions = get_subscriptions(&pic, evm_logs_can_id).await?;
+  assert_eq!(subscriptions.len(), 1);
+  assert_eq!(subscriptions[0].subscription_id, sub_id);
+  assert!(!update_config_succeeds(&pic, evm_logs_can_id, 4_000).await?);
+
+  Ok(())
+}
+
+#[tokio::test]
+async fn test_install_rejects_invalid_config() -> Result<()> {
+  let test_config = TestConfig::new()?;
+  let (pic, evm_logs_can_id) = init_pocket_ic_with_wasm(&test_config.evm_logs_canister_wasm_path).await?;
+  let evm_logs_wasm_bytes = tokio::fs::read(&test_config.evm_logs_canister_wasm_path).await?;
+
+  // responses for 10 events of 800 bytes don't fit into 1000 bytes
+  let init_args = candid::encode_args((EvmLogsInitArgs {
+    evm_rpc_canister: pic.create_canister().await,
+    proxy_canister: pic.create_canister().await,
+    estimate_events_num: 10,
+    max_response_bytes: 1_000,
+  },))?;
+  let reinstall_result = pic
+    .reinstall_canister(evm_logs_can_id, evm_logs_wasm_bytes, init_args, None)
+    .await;
+  assert!(reinstall_result.is_err(), "Install with an invalid config succeeded");
+
+  // the failed install keeps the previous config, 5 events of 800 bytes fit into 4000 bytes
+  assert!(update_config_succeeds(&pic, evm_logs_can_id, 4_000).await?);
+
+  Ok(())
+}
+
+fn registration(subscriber: Principal) -> SubscriptionRegistration {
+  SubscriptionRegistration {
+    chain_id: 8453,
+    filter: Filter {
+      address: Hex20::from_str(ADDR_HEX20).unwrap(),
+      topics: Some(vec![vec![Hex32::from_str(TOPIC_HEX32).unwrap()]]),
+      predicate: None,
+      data_masks: None,
+    },
+    memo: None,
+    canister_to_top_up: subscriber,
+    delivery: None,
+    managers: None,
+    expiry: None,
+    max_events: None,
+    labels: None,
+    callback: None,
+    failure_threshold: None,
+  }
+}
+
+/// Subscribes on behalf of `canister_to_top_up` of the registration
+async fn subscribe(pic: &PocketIc, evm_logs_can_id: CanisterId, registration: SubscriptionRegistration) -> Result<Nat> {
+  let subscriber = registration.canister_to_top_up;
+  let subscribe_result: RegisterSubscriptionResult = update(
+    pic,
+    evm_logs_can_id,
+    subscriber,
+    "subscribe",
+    candid::encode_one(registration)?,
+  )
+  .await?;
+  match subscribe_result {
+    RegisterSubscriptionResult::Ok(sub_id) => Ok(sub_id),
+    err => anyhow::bail!("Subscription failed: {:?}", err),
+  }
+}
+
+/// The canister has no config query, whether `max_response_bytes` is accepted tells the estimated events number apart
+async fn update_config_succeeds(pic: &PocketIc, evm_logs_can_id: CanisterId, max_response_bytes: u32) -> Result<bool> {
+  let config_update = ConfigUpdate {
+    max_response_bytes: Some(max_response_bytes),
+    ..Default::default()
+  };
+  let result: AdminResult = update(
+    pic,
+    evm_logs_can_id,
+    Principal::anonymous(),
+    "update_config",
+    candid::encode_one(config_update)?,
+  )
+  .await?;
+
+  Ok(result == AdminResult::Ok)
+}
+
+async fn get_subscriptions(pic: &PocketIc, evm_logs_can_id: CanisterId) -> Result<Vec<SubscriptionInfo>> {
+  query(
+    pic,
+    evm_logs_can_id,
+    "get_subscriptions",
+    candid::encode_args((None::<u32>, None::<Nat>, None::<Vec<Filter>>))?,
+  )
+  .await
+}
+
+async fn update<T: candid::CandidType + for<'de> candid::Deserialize<'de>>(
+  pic: &PocketIc,
+  canister_id: CanisterId,
+  sender: Principal,
+  method: &str,
+  args: Vec<u8>,
+) -> Result<T> {
+  match pic.update_call(canister_id, sender, method, args).await {
+    Ok(WasmResult::Reply(data)) => Ok(candid::decode_one(&data)?),
+    Ok(WasmResult::Reject(err)) => anyhow::bail!("{} rejected: {:?}", method, err),
+    Err(e) => anyhow::bail!("{} call error: {:?}", method, e),
+  }
+}
+
+async fn query<T: candid::CandidType + for<'de> candid::Deserialize<'de>>(
+  pic: &PocketIc,
+  canister_id: CanisterId,
+  method: &str,
+  args: Vec<u8>,
+) -> Result<T> {
+  match pic.query_call(canister_id, Principal::anonymous(), method, args).await {
+    Ok(WasmResult::Reply(data)) => Ok(candid::decode_one(&data)?),
+    Ok(WasmResult::Reject(err)) => anyhow::bail!("{} rejected: {:?}", method, err),
+    Err(e) => anyhow::bail!("{} call error: {:?}", method, e),
+  }
+}
+
+/// Initializes PocketIc with the evm-logs-canister built from the given wasm, controlled by the anonymous principal.
+/// Returns (pic, evm_logs_can_id).
+async fn init_pocket_ic_with_wasm(evm_logs_wasm_path: &str) -> Result<(PocketIc, CanisterId)> {
+  let pic = PocketIc::new().await;
+
+  let evm_logs_can_id = pic.create_canister().await;
+  // neither is called successfully, the state only has to survive upgrades
+  let evm_rpc_can_id = pic.create_canister().await;
+  let proxy_can_id = pic.create_canister().await;
+
+  pic.add_cycles(evm_logs_can_id, DEFAULT_CYCLES_VALUE).await;
+  let evm_logs_wasm_bytes = tokio::fs::read(evm_logs_wasm_path).await?;
+  let init_args = candid::encode_args((EvmLogsInitArgs {
+    evm_rpc_canister: evm_rpc_can_id,
+    proxy_canister: proxy_can_id,
+    estimate_events_num: 5,
+    max_response_bytes: 10000,
+  },))?;
+  pic
+    .install_canister(evm_logs_can_id, evm_logs_wasm_bytes, init_args, None)
+    .await;
+
+  Ok((pic, evm_logs_can_id))
+}
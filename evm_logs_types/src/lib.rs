@@ -1,5 +1,5 @@
-use candid::{CandidType, Deserialize, Nat, Principal};
-use evm_rpc_types::{Hex20, Hex32, LogEntry};
+use candid::{CandidType, Deserialize, Int, Nat, Principal};
+use evm_rpc_types::{Hex20, Hex32, LogEntry, Nat256};
 use serde::Serialize;
 
 /// A note on specifying topic filters:
@@ -17,6 +17,81 @@ pub type TopicsPosition = Vec<Hex32>;
 pub struct Filter {
   pub address: Hex20,
   pub topics: Option<Vec<TopicsPosition>>, // there is maximum of 4 topics position in the filter
+  pub predicate: Option<EventPredicate>,   // evaluated on ABI-decoded fields after address and topics match
+  pub data_masks: Option<Vec<DataMask>>,   // all of them have to match `LogEntry.data`
+}
+
+/// Byte-level condition on raw `LogEntry.data`, for contracts without a published ABI:
+/// `data[offset..offset + mask.len()] & mask == value & mask`, inverted when `negate` is set.
+///
+/// E.g. "word at offset 64 is non-zero" is `{ offset: 64, mask: [0xff; 32], value: [0; 32], negate: true }`.
+#[derive(CandidType, Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct DataMask {
+  pub offset: u32,
+  pub mask: Vec<u8>,
+  pub value: Vec<u8>,
+  pub negate: bool,
+}
+
+/// ABI type of an event parameter as seen by the predicate evaluator.
+///
+/// Every variant except `Dynamic` is a static type that occupies exactly one 32-byte word.
+/// `Dynamic` (string, bytes, arrays) only keeps the parameter position and can't be compared.
+#[derive(CandidType, Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum AbiParamKind {
+  Address,
+  Bool,
+  Uint,       // uint8..uint256
+  Int,        // int8..int256
+  FixedBytes, // bytes1..bytes32
+  Dynamic,
+}
+
+/// One parameter of the event signature, in declaration order.
+#[derive(CandidType, Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct EventParam {
+  pub name: String,
+  pub kind: AbiParamKind,
+  pub indexed: bool,
+}
+
+#[derive(CandidType, Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum CompareOp {
+  Eq,
+  Ne,
+  Lt,
+  Le,
+  Gt,
+  Ge,
+}
+
+#[derive(CandidType, Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum PredicateValue {
+  Nat(Nat),
+  Int(Int),
+  Bool(bool),
+  Address(Hex20),
+  Bytes(Vec<u8>),
+}
+
+/// Boolean expression over decoded event fields, e.g. `value > 1_000_000e6` or `amount0 < 0`.
+#[derive(CandidType, Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum Predicate {
+  Compare {
+    field: String,
+    op: CompareOp,
+    value: PredicateValue,
+  },
+  And(Vec<Predicate>),
+  Or(Vec<Predicate>),
+  Not(Box<Predicate>),
+}
+
+/// Event layout (needed to decode `topics` and `data`) together with the condition to evaluate.
+#[derive(CandidType, Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct EventPredicate {
+  pub params: Vec<EventParam>,
+  pub condition: Predicate,
 }
 
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
@@ -30,12 +105,57 @@ pub struct Event {
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
 pub struct EventNotification {
   pub sub_id: Nat,
-  pub event_id: Nat,
+  pub event_id: Nat, // the same for every notification of the event, also when it's delivered again
+  pub notification_id: Nat, // unique per notification sent
   pub timestamp: u64,
   pub chain_id: u32,
   pub filter: Option<String>,
   pub source: Principal,
   pub log_entry: LogEntry,
+  pub transaction: Option<TransactionLogs>, // set for DeliveryMode::ByTransaction, `log_entry` is its first log
+  pub memo: Option<Vec<u8>>,                // echoed from the subscription
+  pub labels: Vec<Label>,                   // echoed from the subscription
+}
+
+/// Custom key/value metadata of a subscription, e.g. for routing notifications on the subscriber side.
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct Label {
+  pub key: String,
+  pub value: String,
+}
+
+/// Block context and all matched logs of a single transaction, ordered by `log_index`.
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct TransactionLogs {
+  pub transaction_hash: Option<Hex32>,
+  pub transaction_index: Option<Nat256>,
+  pub block_number: Option<Nat256>,
+  pub block_hash: Option<Hex32>,
+  pub logs: Vec<LogEntry>,
+}
+
+/// Sent to the owner of a subscription when something happens to the subscription itself
+/// (as opposed to `EventNotification`s sent to the subscriber).
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct SubscriptionNotice {
+  pub sub_id: Nat,
+  pub timestamp: u64, // UTC Nanoseconds
+  pub chain_id: u32,
+  pub kind: SubscriptionNoticeKind,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub enum SubscriptionNoticeKind {
+  /// Cancelled after its `Expiry`
+  Expired,
+  /// Cancelled after delivering `max_events` notifications
+  EventLimitReached,
+  /// Suspended after too many consecutive delivery failures
+  Suspended,
+  /// Suspended until the balance is topped up before the deadline
+  LowBalance { deadline: u64 },
+  /// Cancelled after its balance wasn't topped up before the deadline
+  BalanceExhausted,
 }
 
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
@@ -67,15 +187,158 @@ pub struct SubscriptionRegistration {
   pub filter: Filter,
   pub memo: Option<Vec<u8>>, // Blob
   pub canister_to_top_up: Principal,
+  pub delivery: Option<DeliveryMode>,   // DeliveryMode::Immediate if not set
+  pub managers: Option<Vec<Principal>>, // allowed to modify or cancel the subscription besides its owner
+  pub expiry: Option<Expiry>,
+  pub max_events: Option<u64>, // cancel after this many notifications were delivered, e.g. 1 for a one-shot subscription
+  pub labels: Option<Vec<Label>>,
+  pub callback: Option<CallbackTarget>, // `handle_notification` of `canister_to_top_up` if not set
+  pub failure_threshold: Option<u32>,   // consecutive delivery failures before suspension, a default one if not set
+}
+
+/// Where notifications of a subscription are delivered to.
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct CallbackTarget {
+  pub canister: Principal,
+  pub method: String,
+}
+
+/// Point after which a subscription is cancelled automatically.
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub enum Expiry {
+  AtBlock(Nat), // logs of this block are still delivered
+  AtTime(u64),  // UTC Nanoseconds
 }
 
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
 pub struct SubscriptionInfo {
   pub subscription_id: Nat,
   pub subscriber_principal: Principal,
+  pub owner: Principal, // caller that registered the subscription
+  pub managers: Vec<Principal>,
   pub chain_id: u32,
   pub filter: Filter,
   pub stats: Vec<Map>,
+  pub delivery: DeliveryMode,
+  pub status: SubscriptionStatus,
+  pub expiry: Option<Expiry>,
+  pub remaining_events: Option<u64>, // notifications left to deliver before the subscription is cancelled
+  pub memo: Option<Vec<u8>>,
+  pub labels: Vec<Label>,
+  pub callback: Option<CallbackTarget>,
+  pub failure_threshold: u32,
+}
+
+impl SubscriptionInfo {
+  pub fn is_manageable_by(&self, caller: &Principal) -> bool {
+    self.owner == *caller || self.managers.contains(caller)
+  }
+
+  pub fn is_active(&self) -> bool {
+    self.status == SubscriptionStatus::Active
+  }
+
+  /// Whether the filter takes part in fetching (is registered in the filter manager)
+  pub fn is_fetching(&self) -> bool {
+    matches!(
+      self.status,
+      SubscriptionStatus::Active
+        | SubscriptionStatus::Paused {
+          mode: PauseMode::Buffer,
+          ..
+        }
+    )
+  }
+}
+
+/// Delivery statistics of a subscription, also exposed as `SubscriptionInfo.stats` in ICRC-16 `Map` form.
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq, Default)]
+pub struct SubscriptionStats {
+  pub events_matched: u64,
+  pub events_delivered: u64,
+  pub events_failed: u64,  // the notification couldn't be sent
+  pub events_skipped: u64, // matched, but not delivered: replaced by coalescing, dropped while paused, low balance
+  pub cycles_charged: Nat,
+  pub last_delivery_at: Option<u64>, // UTC Nanoseconds
+  pub last_error: Option<String>,
+  pub consecutive_failures: u32, // reset by every successful delivery
+}
+
+/// Criteria of `query_subscriptions`, all set ones have to match.
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, Default)]
+pub struct SubscriptionsQuery {
+  pub chain_id: Option<u32>,
+  pub from_id: Option<Nat>,         // cursor, subscriptions with greater ids are returned
+  pub filters: Option<Vec<Filter>>, // subscription filter equals one of them
+  pub address: Option<Hex20>,
+  pub topic: Option<Hex32>, // in any position of the subscription filter
+  pub owner: Option<Principal>,
+  pub limit: Option<u32>,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct SubscriptionsPage {
+  pub subscriptions: Vec<SubscriptionInfo>, // ordered by id
+  pub next_from_id: Option<Nat>,            // `None` on the last page
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq, Default)]
+pub enum SubscriptionStatus {
+  #[default]
+  Active,
+  Paused {
+    mode: PauseMode,
+    at_block: Option<Nat>, // last block processed for the chain when paused
+  },
+  /// Stopped after `failure_threshold` consecutive delivery failures, neither fetched for nor charged
+  /// until `reactivate_subscription`.
+  Suspended { consecutive_failures: u32 },
+  /// Balance of the subscriber ran out. Neither fetched for nor delivered until a top-up restores it,
+  /// cancelled if that doesn't happen before the deadline.
+  LowBalance { deadline: u64 }, // UTC Nanoseconds
+}
+
+/// What happens with matching logs while a subscription is paused.
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub enum PauseMode {
+  /// The filter is left out of fetching. On resume the missed blocks are fetched for this subscription only.
+  Exclude,
+  /// Matched events are kept (up to a bounded number, oldest dropped first) and delivered on resume.
+  Buffer,
+}
+
+/// How matched events are turned into notifications.
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq, Default)]
+pub enum DeliveryMode {
+  /// Every matched event is delivered in its own notification.
+  #[default]
+  Immediate,
+  /// Within each window only the most recent matched event per key is delivered,
+  /// e.g. the latest Uniswap V2 `Sync` per pool.
+  Coalesce(CoalesceConfig),
+  /// All matched logs of a transaction (e.g. Transfer, Swap and Sync of a router call)
+  /// are delivered together in one notification.
+  ByTransaction,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct CoalesceConfig {
+  pub window: CoalesceWindow,
+  pub key: CoalesceKey,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub enum CoalesceWindow {
+  Blocks(u64),
+  Seconds(u64),
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub enum CoalesceKey {
+  /// Emitting contract address
+  Address,
+  /// Topic at the given position (0..=3)
+  Topic(u8),
 }
 
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
@@ -158,7 +421,25 @@ pub enum RegisterSubscriptionResult {
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
 pub enum UnsubscribeResult {
   Ok(),
-  Err(String),
+  Err(SubscriptionError),
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub enum SubscriptionResult {
+  Ok,
+  Err(SubscriptionError),
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub enum SubscriptionError {
+  Unauthorized,
+  NotFound(Nat), // subscription id
+  ImproperConfig(String),
+  SameFilterExists,
+  NotActive,
+  NotPaused,
+  NotSuspended,
+  BackfillFailed(String), // it stays paused after the last backfilled block, resuming again continues from there
 }
 
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
@@ -183,6 +464,132 @@ pub enum TopUpBalanceError {
   GenericError,
 }
 
+/// Fees charged on top of the cycles spent, all in cycles.
+/// Every charge is scaled by the multiplier of its chain and then by the service margin.
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq, Default)]
+pub struct PricingSchedule {
+  pub delivery_fee: u64,                       // per delivered event
+  pub polling_fee: u64,                        // per address of the `eth_getLogs` request, each monitoring tick
+  pub chain_multipliers: Vec<ChainMultiplier>, // chains without one are priced at 100%
+  pub margin_percent: u32,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct ChainMultiplier {
+  pub chain_id: u32,
+  pub percent: u32,
+}
+
+/// Expected daily cost of a filter under the current pricing, based on the events observed for its
+/// address and topic0. Predicates and data masks aren't taken into account, so delivery is an upper bound.
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct SubscriptionQuote {
+  pub chain_id: u32,
+  pub polling_interval_secs: u64,
+  pub observed_polls: u64, // polls the event rate is based on, 0 if the address was never polled
+  pub events_per_day: u64,
+  pub polling_cycles_per_day: Nat,
+  pub delivery_cycles_per_day: Nat,
+  pub total_cycles_per_day: Nat,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub enum QuoteSubscriptionResult {
+  Ok(SubscriptionQuote),
+  Err(SubscriptionError),
+}
+
+/// ICRC-2 ledger accepted by `deposit`, `amount` of its tokens (in the smallest units)
+/// is credited as `amount * cycles_numerator / cycles_denominator` cycles.
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct PaymentLedger {
+  pub ledger: Principal,
+  pub token_symbol: String,
+  pub cycles_numerator: Nat,
+  pub cycles_denominator: Nat,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub enum DepositResult {
+  Ok(Nat), // credited cycles
+  Err(DepositError),
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub enum DepositError {
+  UnsupportedLedger,
+  AmountTooSmall, // worth less than a cycle
+  TransferFailed(String),
+}
+
+/// Privileges granted by controllers, who implicitly hold every role.
+#[derive(CandidType, Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Role {
+  Operator,  // operational endpoints, e.g. monitoring
+  Publisher, // `publish_events`
+}
+
+/// Changes to the canister config, fields left out keep their current value.
+/// Passed to `update_config` or as the upgrade argument.
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq, Default)]
+pub struct ConfigUpdate {
+  pub evm_rpc_canister: Option<Principal>,
+  pub proxy_canister: Option<Principal>,
+  pub estimate_events_num: Option<u32>,
+  pub max_response_bytes: Option<u32>,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub enum AdminResult {
+  Ok,
+  Err(AdminError),
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub enum AdminError {
+  ImproperConfig(String),
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub enum BalanceChangeKind {
+  TopUp,
+  Deposit { ledger: Principal, block_index: Nat },
+  FetchCharge,
+  DeliveryCharge,
+  Refund { destination: Principal },
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct BalanceLedgerEntry {
+  pub id: u64, // increasing across all principals
+  pub timestamp: u64,
+  pub kind: BalanceChangeKind,
+  pub amount: Nat,
+  pub balance_after: Nat,
+  pub subscription_id: Option<Nat>,
+  pub chain_id: Option<u32>,
+  pub from_block: Option<Nat>,
+  pub to_block: Option<Nat>,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct BalanceHistoryPage {
+  pub entries: Vec<BalanceLedgerEntry>, // ordered by id
+  pub next_from_id: Option<u64>,        // `None` on the last page
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub enum WithdrawBalanceResult {
+  Ok(Nat), // withdrawn cycles
+  Err(WithdrawBalanceError),
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub enum WithdrawBalanceError {
+  InsufficientBalance { balance: Nat },
+  DepositFailed(String),
+}
+
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
 pub enum PublishError {
   Unauthorized,
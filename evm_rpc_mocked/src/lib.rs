@@ -19,6 +19,7 @@ thread_local! {
         evm_logs_canister_id: Principal::anonymous(),
     });
     static ETH_GET_LOGS_COUNTER: RefCell<u64> = RefCell::new(0);
+    static LATEST_BLOCK: RefCell<u64> = const { RefCell::new(123456) };
 }
 
 #[init]
@@ -53,9 +54,16 @@ pub async fn eth_get_block_by_number(
 ) -> MultiRpcResult<evm_rpc_types::Block> {
   debug_log!("CALLING eth_getBlockByNumber");
 
+  // every call sees a new block, so every monitoring tick has a block range to fetch
+  let number = LATEST_BLOCK.with(|latest| {
+    let mut latest = latest.borrow_mut();
+    *latest += 1;
+    *latest
+  });
+
   let block = evm_rpc_types::Block {
     base_fee_per_gas: Some(Nat256::from(10u32)),
-    number: Nat256::from(123456u32),
+    number: Nat256::from(number),
     difficulty: Some(Nat256::from(5000000u32)),
     extra_data: Hex::from(vec![0x01, 0x02, 0x03]),
     gas_limit: Nat256::from(30000000u32),
@@ -1,21 +1,69 @@
-use std::vec::Vec;
+use std::{cell::RefCell, vec::Vec};
 
-use candid::{Principal, candid_method};
+use candid::{CandidType, Deserialize, Principal, candid_method};
 use canister_utils::debug_log;
-use evm_logs_types::{EventNotification, SendNotificationError, SendNotificationResult};
-use ic_cdk::api::call::call;
-use ic_cdk_macros::{init, query, update};
+use evm_logs_types::{EventNotification, SendNotificationError, SendNotificationResult, SubscriptionNotice};
+use ic_cdk::{api::call::call, caller, storage};
+use ic_cdk_macros::{init, post_upgrade, pre_upgrade, query, update};
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct Config {
+  pub evm_logs_canister: Principal, // the only canister whose calls are relayed
+}
+
+thread_local! {
+    static CONFIG: RefCell<Config> = const {
+        RefCell::new(Config {
+            evm_logs_canister: Principal::anonymous(),
+        })
+    };
+}
 
 #[init]
-async fn init() {
+async fn init(config: Config) {
+  CONFIG.with(|c| *c.borrow_mut() = config);
   debug_log!("Proxy canister initialized");
 }
 
-#[update(name = "send_notification")]
+#[pre_upgrade]
+fn pre_upgrade() {
+  let config = CONFIG.with(|c| c.borrow().clone());
+  storage::stable_save((config,)).expect("Failed to save the config");
+}
+
+/// Proxies deployed before the config was introduced have nothing saved and have to be upgraded with it
+#[post_upgrade]
+fn post_upgrade(config: Option<Config>) {
+  let config = config.unwrap_or_else(|| {
+    let (config,): (Config,) = storage::stable_restore().expect("No config saved, upgrade with the config argument");
+    config
+  });
+  CONFIG.with(|c| *c.borrow_mut() = config);
+}
+
+/// Anyone else could make the proxy call arbitrary canisters on its behalf
+fn caller_is_evm_logs_canister() -> Result<(), String> {
+  if CONFIG.with(|c| c.borrow().evm_logs_canister == caller()) {
+    Ok(())
+  } else {
+    Err("Only the evm_logs canister can call this method".to_string())
+  }
+}
+
+/// Method of the subscriber called if the subscription has no callback method set
+const DEFAULT_CALLBACK_METHOD: &str = "handle_notification";
+
+#[update(name = "send_notification", guard = "caller_is_evm_logs_canister")]
 #[candid_method(update)]
-async fn send_notification(subscriber: Principal, notification: EventNotification) -> SendNotificationResult {
+async fn send_notification(
+  subscriber: Principal,
+  notification: EventNotification,
+  method: Option<String>,
+) -> SendNotificationResult {
+  let method = method.unwrap_or_else(|| DEFAULT_CALLBACK_METHOD.to_string());
+
   // Send the notification to the subscriber
-  let call_result: Result<(), String> = call(subscriber, "handle_notification", (notification.clone(),))
+  let call_result: Result<(), String> = call(subscriber, &method, (notification.clone(),))
     .await
     .map_err(|e| format!("Transport or call error: {:?}", e));
 
@@ -28,4 +76,21 @@ async fn send_notification(subscriber: Principal, notification: EventNotificatio
   }
 }
 
+#[update(name = "send_subscription_notice", guard = "caller_is_evm_logs_canister")]
+#[candid_method(update)]
+async fn send_subscription_notice(owner: Principal, notice: SubscriptionNotice) -> SendNotificationResult {
+  // Send the notice to the owner of the subscription
+  let call_result: Result<(), String> = call(owner, "handle_subscription_notice", (notice,))
+    .await
+    .map_err(|e| format!("Transport or call error: {:?}", e));
+
+  match call_result {
+    Ok(_) => SendNotificationResult::Ok,
+    Err(err_msg) => {
+      debug_log!("Error sending subscription notice: {}", err_msg);
+      SendNotificationResult::Err(SendNotificationError::FailedToSend)
+    }
+  }
+}
+
 ic_cdk::export_candid!();
@@ -5,7 +5,10 @@ pub mod utils;
 
 use candid::{CandidType, Deserialize, Nat, Principal};
 use decoders::{chainfusion_deposit_decoder, ethereum_sync_decoder, primex_deposit_decoder, swap_event_data_decoder};
-use evm_logs_types::{EventNotification, RegisterSubscriptionResult, TopUpBalanceResult, UnsubscribeResult};
+use evm_logs_types::{
+  EventNotification, RegisterSubscriptionResult, SubscriptionNotice, SubscriptionRegistration, TopUpBalanceResult,
+  UnsubscribeResult,
+};
 use ic_cdk::api::{
   call::{call, call_with_payment, call_with_payment128},
   canister_balance128,
@@ -121,6 +124,18 @@ async fn subscribe_fantom_token(evm_logs_canister: Principal) {
   register_subscription_and_map_decoder(evm_logs_canister, fantom_token_config, mainnet_fantom_token).await;
 }
 
+/// Registers the subscription on its `canister_to_top_up`, which has to be this canister, paying `cycles` for it
+#[update]
+async fn register_subscription(
+  evm_logs_canister: Principal,
+  registration: SubscriptionRegistration,
+  cycles: u128,
+) -> RegisterSubscriptionResult {
+  let result: Result<(RegisterSubscriptionResult,), _> =
+    call_with_payment128(evm_logs_canister, "subscribe", (registration,), cycles).await;
+  result.expect("Error calling evm-logs-canister").0
+}
+
 #[update]
 async fn unsubscribe(canister_id: Principal, subscription_id: candid::Nat) {
   log_with_metrics!("Calling unsubscribe for subscription ID: {:?}", subscription_id);
@@ -169,6 +184,15 @@ async fn handle_notification(notification: EventNotification) {
   });
 }
 
+#[update]
+async fn handle_subscription_notice(notice: SubscriptionNotice) {
+  log_with_metrics!(
+    "Received notice for subscription ID {:?}: {:?}",
+    notice.sub_id,
+    notice.kind
+  );
+}
+
 #[update]
 async fn top_up_evm_logs_canister(evm_logs_canister: Principal, cycles: u128) {
   log_with_metrics!("Topping up balance of evm canister '{evm_logs_canister}' with {cycles} cycles");
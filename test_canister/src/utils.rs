@@ -53,6 +53,8 @@ pub fn create_base_swaps_config() -> SubscriptionRegistration {
   let filter = Filter {
     address: Hex20::from(address),
     topics,
+    predicate: None,
+    data_masks: None,
   };
 
   SubscriptionRegistration {
@@ -60,6 +62,13 @@ pub fn create_base_swaps_config() -> SubscriptionRegistration {
     filter,
     memo: None,
     canister_to_top_up: ic_cdk::id(),
+    delivery: None,
+    managers: None,
+    expiry: None,
+    max_events: None,
+    labels: None,
+    callback: None,
+    failure_threshold: None,
   }
 }
 
@@ -69,13 +78,25 @@ pub fn create_ethereum_sync_config() -> SubscriptionRegistration {
     Hex32::from_str("0x1c411e9a96e071241c2f21f7726b17ae89e3cab4c78be50e062b03a9fffbbad1").unwrap(),
   ]]);
 
-  let filter = Filter { address, topics };
+  let filter = Filter {
+    address,
+    topics,
+    predicate: None,
+    data_masks: None,
+  };
 
   SubscriptionRegistration {
     chain_id: 1,
     filter,
     memo: None,
     canister_to_top_up: ic_cdk::id(),
+    delivery: None,
+    managers: None,
+    expiry: None,
+    max_events: None,
+    labels: None,
+    callback: None,
+    failure_threshold: None,
   }
 }
 
@@ -85,13 +106,25 @@ pub fn create_primex_deposit_config() -> SubscriptionRegistration {
     Hex32::from_str("0x5548c837ab068cf56a2c2479df0882a4922fd203edb7517321831d95078c5f62").unwrap(),
   ]]);
 
-  let filter = Filter { address, topics };
+  let filter = Filter {
+    address,
+    topics,
+    predicate: None,
+    data_masks: None,
+  };
 
   SubscriptionRegistration {
     chain_id: 137,
     filter,
     memo: None,
     canister_to_top_up: ic_cdk::id(),
+    delivery: None,
+    managers: None,
+    expiry: None,
+    max_events: None,
+    labels: None,
+    callback: None,
+    failure_threshold: None,
   }
 }
 
@@ -101,13 +134,25 @@ pub fn create_chainfusion_deposit_config() -> SubscriptionRegistration {
     Hex32::from_str("0x257e057bb61920d8d0ed2cb7b720ac7f9c513cd1110bc9fa543079154f45f435").unwrap(),
   ]]);
 
-  let filter = Filter { address, topics };
+  let filter = Filter {
+    address,
+    topics,
+    predicate: None,
+    data_masks: None,
+  };
 
   SubscriptionRegistration {
     chain_id: 1,
     filter,
     memo: None,
     canister_to_top_up: ic_cdk::id(),
+    delivery: None,
+    managers: None,
+    expiry: None,
+    max_events: None,
+    labels: None,
+    callback: None,
+    failure_threshold: None,
   }
 }
 
@@ -117,13 +162,25 @@ pub fn create_curve_token_exchange_config() -> SubscriptionRegistration {
     Hex32::from_str("0xb2e76ae99761dc136e598d4a629bb347eccb9532a5f8bbd72e18467c3c34cc98").unwrap(),
   ]]);
 
-  let filter = Filter { address, topics };
+  let filter = Filter {
+    address,
+    topics,
+    predicate: None,
+    data_masks: None,
+  };
 
   SubscriptionRegistration {
     chain_id: 137,
     filter,
     memo: None,
     canister_to_top_up: ic_cdk::id(),
+    delivery: None,
+    managers: None,
+    expiry: None,
+    max_events: None,
+    labels: None,
+    callback: None,
+    failure_threshold: None,
   }
 }
 
@@ -133,13 +190,25 @@ pub fn create_uniswap_exchange_1_config() -> SubscriptionRegistration {
     Hex32::from_str("0xc42079f94a6350d7e6235f29174924f928cc2ac818eb64fed8004e115fbcca67").unwrap(),
   ]]);
 
-  let filter = Filter { address, topics };
+  let filter = Filter {
+    address,
+    topics,
+    predicate: None,
+    data_masks: None,
+  };
 
   SubscriptionRegistration {
     chain_id: 1,
     filter,
     memo: None,
     canister_to_top_up: ic_cdk::id(),
+    delivery: None,
+    managers: None,
+    expiry: None,
+    max_events: None,
+    labels: None,
+    callback: None,
+    failure_threshold: None,
   }
 }
 
@@ -149,13 +218,25 @@ pub fn create_fantom_token_config() -> SubscriptionRegistration {
     Hex32::from_str("0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef").unwrap(),
   ]]);
 
-  let filter = Filter { address, topics };
+  let filter = Filter {
+    address,
+    topics,
+    predicate: None,
+    data_masks: None,
+  };
 
   SubscriptionRegistration {
     chain_id: 1,
     filter,
     memo: None,
     canister_to_top_up: ic_cdk::id(),
+    delivery: None,
+    managers: None,
+    expiry: None,
+    max_events: None,
+    labels: None,
+    callback: None,
+    failure_threshold: None,
   }
 }
 